@@ -10,13 +10,13 @@ use crate::{
 //   + Enum impls to accept visitors and shorthands for variant construction
 generate_ast! {
     Expr {
-        Binary { left: Box<Expr>, operator: Token, right: Box<Expr> },
+        Binary { left: Box<Expr>, operator: Token<'static>, right: Box<Expr> },
         Grouping { expr: Box<Expr> },
-        Unary { operator: Token, expr: Box<Expr> },
+        Unary { operator: Token<'static>, expr: Box<Expr> },
         Literal { value: Option<Value> }
     },
 
     Stmt {
-        VarDec { name: Token, initializer: Option<Box<Expr>> }
+        VarDec { name: Token<'static>, initializer: Option<Box<Expr>> }
     }
 }
@@ -14,20 +14,39 @@ pub enum OpCode {
     OpSetLocal,
     OpGetGlobal,
     OpDefineGlobal,
+    OpDefineConstGlobal,
     OpSetGlobal,
+    OpGetProperty,
+    OpIndex,
+    OpImport,
+    OpExport,
     OpEqual,
     OpGreater,
+    OpGreaterEqual,
     OpLess,
+    OpLessEqual,
     OpAdd,
     OpSubtract,
     OpMultiply,
     OpDivide,
+    OpRange,
     OpNot,
     OpNegate,
     OpPrint,
+    OpStringify,
     OpJump,
     OpJumpIfFalse,
+    OpJumpIfNil,
     OpLoop,
+    OpCall,
+    OpIsVariant,
+    OpIs,
+    OpGetVariantField,
+    OpNoMatch,
+    OpTry,
+    OpPopHandler,
+    OpThrow,
+    OpEndFinally,
     OpReturn,
 }
 
@@ -1,4 +1,4 @@
-use std::{mem, collections::HashMap, io::Write};
+use std::{mem, collections::{HashMap, HashSet}, io::Write};
 
 use crate::{
     chunk::{Chunk, OpCode},
@@ -6,7 +6,8 @@ use crate::{
     obj::Obj,
     scanner::Scanner,
     token::{Token, TokenType},
-    value::Value,
+    types::StaticType,
+    value::{Value, TYPE_NAMES},
 };
 
 #[repr(u8)]
@@ -14,10 +15,12 @@ use crate::{
 pub enum Precedence {
     None,
     Assignment,
+    Coalesce,
     Or,
     And,
     Equality,
     Comparison,
+    Range,
     Term,
     Factor,
     Unary,
@@ -29,11 +32,13 @@ impl Precedence {
     fn greater(self) -> Self {
         match self {
             Precedence::None => Self::Assignment,
-            Precedence::Assignment => Self::Or,
+            Precedence::Assignment => Self::Coalesce,
+            Precedence::Coalesce => Self::Or,
             Precedence::Or => Self::And,
             Precedence::And => Self::Equality,
             Precedence::Equality => Self::Comparison,
-            Precedence::Comparison => Self::Term,
+            Precedence::Comparison => Self::Range,
+            Precedence::Range => Self::Term,
             Precedence::Term => Self::Factor,
             Precedence::Factor => Self::Unary,
             Precedence::Unary => Self::Call,
@@ -43,19 +48,19 @@ impl Precedence {
     }
 }
 
-struct ParseRule {
-    prefix: Option<fn(&mut Parser, bool, &mut ParserState)>,
-    infix: Option<fn(&mut Parser, bool, &mut ParserState)>,
+struct ParseRule<'a> {
+    prefix: Option<fn(&mut Parser<'a>, bool, &mut ParserState)>,
+    infix: Option<fn(&mut Parser<'a>, bool, &mut ParserState)>,
     precedence: Precedence,
 }
 
-impl ParseRule {
-    fn get_rule(token_type: &TokenType) -> ParseRule {
+impl<'a> ParseRule<'a> {
+    fn get_rule(token_type: &TokenType) -> ParseRule<'a> {
         match token_type {
             TokenType::LeftParen => Self {
                 prefix: Some(Parser::grouping),
-                infix: None,
-                precedence: Precedence::None,
+                infix: Some(Parser::call),
+                precedence: Precedence::Call,
             },
             TokenType::RightParen => Self {
                 prefix: None,
@@ -63,6 +68,16 @@ impl ParseRule {
                 precedence: Precedence::None,
             },
             TokenType::LeftBrace => Self {
+                prefix: Some(Parser::block_expression),
+                infix: None,
+                precedence: Precedence::None,
+            },
+            TokenType::LeftBracket => Self {
+                prefix: None,
+                infix: Some(Parser::index),
+                precedence: Precedence::Call,
+            },
+            TokenType::RightBracket => Self {
                 prefix: None,
                 infix: None,
                 precedence: Precedence::None,
@@ -72,16 +87,26 @@ impl ParseRule {
                 infix: None,
                 precedence: Precedence::None,
             },
-            TokenType::Comma => Self {
+            TokenType::Colon => Self {
                 prefix: None,
                 infix: None,
                 precedence: Precedence::None,
             },
-            TokenType::Dot => Self {
+            TokenType::Comma => Self {
                 prefix: None,
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::DotDot | TokenType::DotDotEqual => Self {
+                prefix: Some(Parser::open_range),
+                infix: Some(Parser::range),
+                precedence: Precedence::Range,
+            },
+            TokenType::Dot => Self {
+                prefix: None,
+                infix: Some(Parser::dot),
+                precedence: Precedence::Call,
+            },
             TokenType::Minus => Self {
                 prefix: Some(Parser::unary),
                 infix: Some(Parser::binary),
@@ -127,6 +152,11 @@ impl ParseRule {
                 infix: Some(Parser::binary),
                 precedence: Precedence::Comparison,
             },
+            TokenType::EqualGreater => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::Greater => Self {
                 prefix: None,
                 infix: Some(Parser::binary),
@@ -157,6 +187,11 @@ impl ParseRule {
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::Interpolation => Self {
+                prefix: Some(Parser::interpolation),
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::Number => Self {
                 prefix: Some(Parser::number),
                 infix: None,
@@ -167,21 +202,51 @@ impl ParseRule {
                 infix: Some(Parser::and_),
                 precedence: Precedence::And,
             },
+            TokenType::As => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
+            TokenType::Catch => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::Class => Self {
                 prefix: None,
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::Const => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::Else => Self {
                 prefix: None,
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::Enum => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
+            TokenType::Export => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::False => Self {
                 prefix: Some(Parser::literal),
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::Finally => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::Fun => Self {
                 prefix: None,
                 infix: None,
@@ -193,10 +258,25 @@ impl ParseRule {
                 precedence: Precedence::None,
             },
             TokenType::If => Self {
+                prefix: Some(Parser::if_expression),
+                infix: None,
+                precedence: Precedence::None,
+            },
+            TokenType::Import => Self {
                 prefix: None,
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::Is => Self {
+                prefix: None,
+                infix: Some(Parser::is_),
+                precedence: Precedence::Comparison,
+            },
+            TokenType::Match => Self {
+                prefix: Some(Parser::pattern_match),
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::Nil => Self {
                 prefix: Some(Parser::literal),
                 infix: None,
@@ -207,6 +287,16 @@ impl ParseRule {
                 infix: Some(Parser::or_),
                 precedence: Precedence::Or,
             },
+            TokenType::QuestionDot => Self {
+                prefix: None,
+                infix: Some(Parser::optional_dot),
+                precedence: Precedence::Call,
+            },
+            TokenType::QuestionQuestion => Self {
+                prefix: None,
+                infix: Some(Parser::coalesce),
+                precedence: Precedence::Coalesce,
+            },
             TokenType::Print => Self {
                 prefix: None,
                 infix: None,
@@ -227,11 +317,21 @@ impl ParseRule {
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::Throw => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::True => Self {
                 prefix: Some(Parser::literal),
                 infix: None,
                 precedence: Precedence::None,
             },
+            TokenType::Try => Self {
+                prefix: None,
+                infix: None,
+                precedence: Precedence::None,
+            },
             TokenType::Var => Self {
                 prefix: None,
                 infix: None,
@@ -251,12 +351,17 @@ impl ParseRule {
     }
 }
 
-pub struct Parser {
-    current: Token,
-    previous: Token,
-    scanner: Scanner,
+pub struct Parser<'a> {
+    current: Token<'a>,
+    previous: Token<'a>,
+    scanner: Scanner<'a>,
     had_error: bool,
     panic_mode: bool,
+    // Whether values that don't match the annotated type of the variable
+    // they're stored in are compile errors. Annotations are ignored otherwise.
+    typecheck: bool,
+    // Static type of the expression that was just compiled
+    expr_type: StaticType,
 }
 
 type GlobIdentifierTable = HashMap<String, u8>;
@@ -264,29 +369,47 @@ type GlobIdentifierTable = HashMap<String, u8>;
 struct ParserState<'a> {
     chunk: &'a mut Chunk,
     global_idents: GlobIdentifierTable,
+    // Constant globals declared in this compilation. The VM keeps track of
+    // the ones declared by earlier REPL lines.
+    const_globals: HashSet<String>,
+    // Annotated types of the globals declared in this compilation
+    global_types: HashMap<String, StaticType>,
     current: Compiler
 }
 
 impl<'a> ParserState<'a> {
     fn new(chunk: &'a mut Chunk) -> Self {
-        ParserState { chunk: chunk, global_idents: HashMap::new(), current: Compiler::new() }
+        ParserState {
+            chunk: chunk,
+            global_idents: HashMap::new(),
+            const_globals: HashSet::new(),
+            global_types: HashMap::new(),
+            current: Compiler::new(),
+        }
     }
 }
 
 struct Compiler {
     locals: Vec<Local>,
     scope_depth: u8,
+    // Height of the VM stack at the point being compiled: the live locals
+    // plus the temporaries of the expressions being compiled around it.
+    // Locals declared inside an expression (like the ones of a `match`)
+    // sit above those temporaries.
+    stack_height: usize,
 }
 
 impl Compiler {
     fn new() -> Self {
-        Self { locals: Vec::new(), scope_depth: 0 }
+        Self { locals: Vec::new(), scope_depth: 0, stack_height: 0 }
     }
 
-    fn add_local(&mut self, local: Local) -> bool {
-        if self.locals.len() == 256 {
+    // The local's value will be pushed at the current stack height
+    fn add_local(&mut self, mut local: Local) -> bool {
+        if self.locals.len() == 256 || self.stack_height > u8::MAX as usize {
             return false;
         }
+        local.slot = self.stack_height as u8;
         self.locals.push(local);
         return true;
     }
@@ -296,31 +419,40 @@ impl Compiler {
 struct Local {
     name: String,
     depth: u8,
+    is_const: bool,
+    slot: u8,
+    annotation: StaticType,
 }
 
 impl Local {
     fn new(name: String, depth: u8) -> Self {
-        Self { name, depth }
+        Self { name, depth, is_const: false, slot: 0, annotation: StaticType::Any }
     }
 }
 
 impl Default for Local {
     fn default() -> Self {
-        Self { name: String::default(), depth: 0 }
+        Self::new(String::default(), 0)
     }
 }
 
-impl Parser {
-    pub fn new(source: String) -> Parser {
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Parser<'a> {
         Parser {
             current: Token::default(),
             previous: Token::default(),
             scanner: Scanner::new(source),
             had_error: false,
             panic_mode: false,
+            typecheck: false,
+            expr_type: StaticType::Any,
         }
     }
 
+    pub fn set_typecheck(&mut self, typecheck: bool) {
+        self.typecheck = typecheck;
+    }
+
     fn advance(&mut self) {
         self.previous = mem::take(&mut self.current);
         loop {
@@ -371,11 +503,16 @@ impl Parser {
 
     fn var_declaration(&mut self, parser_state: &mut ParserState) {
         let global = self.parse_variable(parser_state, "Expect variable name.");
-        
+        let name = self.previous.lexeme.to_string();
+        let annotation = self.type_annotation(name.clone(), parser_state);
+
         if self.match_(TokenType::Equal) {
             self.expression(parser_state);
+            self.check_type(&name, annotation);
         } else {
             self.emit_byte(OpCode::OpNil.into(), parser_state.chunk);
+            self.expr_type = StaticType::Nil;
+            self.check_type(&name, annotation);
         }
 
         self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.");
@@ -383,10 +520,185 @@ impl Parser {
         self.define_variable(global, parser_state);
     }
 
-    fn expression_statement(&mut self, parser_state: &mut ParserState) {
+    fn const_declaration(&mut self, parser_state: &mut ParserState) {
+        let global = self.parse_variable(parser_state, "Expect constant name.");
+        let name = self.previous.lexeme.to_string();
+        if parser_state.current.scope_depth > 0 {
+            if let Some(local) = parser_state.current.locals.last_mut() {
+                local.is_const = true;
+            }
+        }
+        let annotation = self.type_annotation(name.clone(), parser_state);
+
+        self.consume(TokenType::Equal, "Expect '=' after constant name.");
         self.expression(parser_state);
-        self.consume(TokenType::Semicolon, "Expect ';' after expression");
-        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        self.check_type(&name, annotation);
+        self.consume(TokenType::Semicolon, "Expect ';' after constant declaration.");
+
+        if parser_state.current.scope_depth > 0 {
+            self.mark_initialized(parser_state);
+            return;
+        }
+        parser_state.const_globals.insert(name);
+        self.emit_bytes(OpCode::OpDefineConstGlobal.into(), global, parser_state.chunk);
+    }
+
+    fn enum_declaration(&mut self, parser_state: &mut ParserState) {
+        let global = self.parse_variable(parser_state, "Expect enum name.");
+        let enum_name = self.previous.lexeme.to_string();
+        // `value is Name` always means the built-in type
+        if TYPE_NAMES.contains(&enum_name.as_str()) {
+            self.error(&format!("Can't name an enum after the built-in type '{}'.", enum_name));
+        }
+        self.consume(TokenType::LeftBrace, "Expect '{' before enum body.");
+
+        let mut variants: Vec<(String, Vec<String>)> = vec![];
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            self.consume(TokenType::Identifier, "Expect variant name.");
+            let name = self.previous.lexeme.to_string();
+            if variants.iter().any(|(existing, _)| existing == &name) {
+                self.error("Already a variant with this name in this enum.");
+            }
+
+            let mut fields: Vec<String> = vec![];
+            if self.match_(TokenType::LeftParen) {
+                loop {
+                    self.consume(TokenType::Identifier, "Expect field name.");
+                    let field = self.previous.lexeme.to_string();
+                    if field == "variant" {
+                        self.error("Can't use 'variant' as a field name.");
+                    } else if fields.contains(&field) {
+                        self.error("Already a field with this name in this variant.");
+                    }
+                    fields.push(field);
+                    if !self.match_(TokenType::Comma) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightParen, "Expect ')' after variant fields.");
+            }
+            variants.push((name, fields));
+
+            if !self.match_(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after enum body.");
+
+        // The whole enum is known at compile time, so it's just a constant
+        let value = Value::from(Obj::Enum { name: enum_name, variants });
+        self.emit_constant(value, parser_state.chunk);
+        self.define_variable(global, parser_state);
+    }
+
+    fn export_declaration(&mut self, parser_state: &mut ParserState) {
+        if parser_state.current.scope_depth > 0 {
+            self.error("Can only export top-level declarations.");
+        }
+
+        // The name is the token after `var`, `const` or `enum`
+        let name = if self.match_(TokenType::Var) {
+            let name = self.current.lexeme.to_string();
+            self.var_declaration(parser_state);
+            name
+        } else if self.match_(TokenType::Const) {
+            let name = self.current.lexeme.to_string();
+            self.const_declaration(parser_state);
+            name
+        } else if self.match_(TokenType::Enum) {
+            let name = self.current.lexeme.to_string();
+            self.enum_declaration(parser_state);
+            name
+        } else {
+            self.error_at_current("Expect 'var', 'const' or 'enum' after 'export'.");
+            return;
+        };
+
+        let global = self.identifier_constant(name, parser_state);
+        self.emit_bytes(OpCode::OpExport.into(), global, parser_state.chunk);
+    }
+
+    fn import_declaration(&mut self, parser_state: &mut ParserState) {
+        self.consume(TokenType::String, "Expect module path after 'import'.");
+        let str_len = self.previous.lexeme.len();
+        let path = self.previous.lexeme[1..str_len - 1].to_string();
+        let path_constant = self.make_constant(Value::from(Obj::from(path)), parser_state.chunk);
+
+        self.consume(TokenType::As, "Expect 'as' after module path.");
+        let global = self.parse_variable(parser_state, "Expect module name after 'as'.");
+        self.emit_bytes(OpCode::OpImport.into(), path_constant, parser_state.chunk);
+        self.consume(TokenType::Semicolon, "Expect ';' after import.");
+        self.define_variable(global, parser_state);
+    }
+
+    fn expression_statement(&mut self, parser_state: &mut ParserState) {
+        if self.statement_expression(parser_state) {
+            self.consume(TokenType::Semicolon, "Expect ';' after expression");
+            self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        }
+    }
+
+    // Compiles the expression of an expression statement, or a whole
+    // `a, b = ...;` which leaves nothing on the stack. Returns whether
+    // there is a value to pop.
+    fn statement_expression(&mut self, parser_state: &mut ParserState) -> bool {
+        // `a, b = ...` can only be told apart from an expression starting
+        // with a variable once the name has been consumed
+        if self.match_(TokenType::Identifier) {
+            if self.check(TokenType::Comma) {
+                self.multiple_assignment(parser_state);
+                return false;
+            }
+            self.variable(true, parser_state);
+            self.parse_infix(Precedence::Assignment, true, parser_state);
+        } else {
+            self.expression(parser_state);
+        }
+        true
+    }
+
+    // a, b = b, a;
+    // Every value is evaluated before any variable is assigned
+    fn multiple_assignment(&mut self, parser_state: &mut ParserState) {
+        let mut targets = vec![self.previous.lexeme.to_string()];
+        while self.match_(TokenType::Comma) {
+            self.consume(TokenType::Identifier, "Expect variable name.");
+            targets.push(self.previous.lexeme.to_string());
+        }
+        self.consume(TokenType::Equal, "Expect '=' after assignment targets.");
+
+        let stack_height = parser_state.current.stack_height;
+        let mut value_count = 0;
+        let mut value_types = vec![];
+        loop {
+            self.expression(parser_state);
+            value_types.push(self.expr_type);
+            value_count += 1;
+            parser_state.current.stack_height += 1;
+            if !self.match_(TokenType::Comma) {
+                break;
+            }
+        }
+        parser_state.current.stack_height = stack_height;
+        if value_count != targets.len() {
+            self.error(&format!(
+                "Expected {} values but got {}.",
+                targets.len(),
+                value_count
+            ));
+        }
+        // The last value is on top of the stack
+        for (name, value_type) in targets.into_iter().zip(value_types).rev() {
+            let (_, set_op, arg, is_const) = self.resolve_variable(&name, parser_state);
+            if is_const {
+                self.error(&format!("Can't assign to constant '{}'.", name));
+            }
+            self.expr_type = value_type;
+            self.check_type(&name, self.variable_type(&name, parser_state));
+            self.emit_bytes(set_op, arg, parser_state.chunk);
+            self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after assignment.");
     }
 
     fn for_statement(&mut self, parser_state: &mut ParserState) {
@@ -469,6 +781,110 @@ impl Parser {
         self.emit_byte(OpCode::OpPrint.into(), parser_state.chunk);
     }
 
+    fn throw_statement(&mut self, parser_state: &mut ParserState) {
+        self.expression(parser_state);
+        self.emit_byte(OpCode::OpThrow.into(), parser_state.chunk);
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value.");
+    }
+
+    // try { A } catch (e) { B } finally { C } compiles to
+    //
+    //           OpTry -> catch
+    //           A
+    //           OpPopHandler
+    //           OpJump -> normal
+    //   catch:  OpTry -> failed            [e, line]
+    //           B
+    //           OpPopHandler
+    //           OpPop, OpPop
+    //           OpJump -> normal
+    //   failed: OpSetLocal line, OpPop     [e, line, thrown, line2]
+    //           OpSetLocal e, OpPop        -> [thrown, line2]
+    //           OpJump -> finally
+    //   normal: OpNil, OpFalse             [nil, false]
+    //   finally:
+    //           C
+    //           OpEndFinally               rethrows if there is a line
+    //
+    // The VM pushes the exception and the line it was thrown from when it
+    // jumps to a handler, so a rethrow reports the original line. Without
+    // a catch clause the exception goes straight to `failed`, without a
+    // finally clause `failed` is just an OpEndFinally.
+    fn try_statement(&mut self, parser_state: &mut ParserState) {
+        let try_jump = self.emit_jump(OpCode::OpTry.into(), parser_state);
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.");
+        self.begin_scope(parser_state);
+        self.block(parser_state);
+        self.end_scope(parser_state);
+        self.emit_byte(OpCode::OpPopHandler.into(), parser_state.chunk);
+        let mut normal_jumps = vec![self.emit_jump(OpCode::OpJump.into(), parser_state)];
+
+        // The VM pushes the exception before jumping here
+        self.patch_jump(try_jump, parser_state);
+
+        let has_catch = self.match_(TokenType::Catch);
+        if has_catch {
+            let exception_slot = parser_state.current.stack_height as u8;
+            self.begin_scope(parser_state);
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.");
+            self.consume(TokenType::Identifier, "Expect exception variable name.");
+            self.declare_variable(parser_state);
+            self.mark_initialized(parser_state);
+            self.add_hidden_local(parser_state);
+            self.consume(TokenType::RightParen, "Expect ')' after exception variable.");
+
+            let catch_jump = self.emit_jump(OpCode::OpTry.into(), parser_state);
+            self.consume(TokenType::LeftBrace, "Expect '{' after catch clause.");
+            self.begin_scope(parser_state);
+            self.block(parser_state);
+            self.end_scope(parser_state);
+            self.emit_byte(OpCode::OpPopHandler.into(), parser_state.chunk);
+            self.end_scope(parser_state);
+            normal_jumps.push(self.emit_jump(OpCode::OpJump.into(), parser_state));
+
+            // An exception escaped the catch block, replace the caught one with it
+            self.patch_jump(catch_jump, parser_state);
+            self.emit_bytes(OpCode::OpSetLocal.into(), exception_slot + 1, parser_state.chunk);
+            self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+            self.emit_bytes(OpCode::OpSetLocal.into(), exception_slot, parser_state.chunk);
+            self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        }
+
+        if !self.match_(TokenType::Finally) {
+            if !has_catch {
+                self.error_at_current("Expect 'catch' or 'finally' after try block.");
+            }
+            self.emit_byte(OpCode::OpEndFinally.into(), parser_state.chunk);
+            for jump in normal_jumps {
+                self.patch_jump(jump, parser_state);
+            }
+            return;
+        }
+
+        let finally_jump = self.emit_jump(OpCode::OpJump.into(), parser_state);
+        for jump in normal_jumps {
+            self.patch_jump(jump, parser_state);
+        }
+        self.emit_bytes(OpCode::OpNil.into(), OpCode::OpFalse.into(), parser_state.chunk);
+        self.patch_jump(finally_jump, parser_state);
+
+        // The pending exception and its line sit below the finally
+        // block's locals. They can't be named from lox code and OpEndFinally
+        // pops them itself.
+        self.begin_scope(parser_state);
+        self.add_hidden_local(parser_state);
+        self.add_hidden_local(parser_state);
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'.");
+        self.begin_scope(parser_state);
+        self.block(parser_state);
+        self.end_scope(parser_state);
+        self.emit_byte(OpCode::OpEndFinally.into(), parser_state.chunk);
+        parser_state.current.scope_depth -= 1;
+        let local_count = parser_state.current.locals.len();
+        parser_state.current.locals.truncate(local_count.saturating_sub(2));
+        parser_state.current.stack_height -= 2;
+    }
+
     fn while_statement(&mut self, parser_state: &mut ParserState) {
         let loop_start = parser_state.chunk.code.len();
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
@@ -493,13 +909,19 @@ impl Parser {
             }
             match self.current.token_type {
                 TokenType::Class
+                | TokenType::Const
+                | TokenType::Enum
+                | TokenType::Export
                 | TokenType::Fun
+                | TokenType::Import
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try => return,
                 _ => {}
             }
             self.advance();
@@ -509,6 +931,14 @@ impl Parser {
     fn declaration(&mut self, parser_state: &mut ParserState) {
         if self.match_(TokenType::Var) {
             self.var_declaration(parser_state);
+        } else if self.match_(TokenType::Const) {
+            self.const_declaration(parser_state);
+        } else if self.match_(TokenType::Enum) {
+            self.enum_declaration(parser_state);
+        } else if self.match_(TokenType::Export) {
+            self.export_declaration(parser_state);
+        } else if self.match_(TokenType::Import) {
+            self.import_declaration(parser_state);
         } else {
             self.statement(parser_state);
         }
@@ -527,6 +957,10 @@ impl Parser {
             self.if_statement(parser_state);
         } else if self.match_(TokenType::While) {
             self.while_statement(parser_state);
+        } else if self.match_(TokenType::Throw) {
+            self.throw_statement(parser_state);
+        } else if self.match_(TokenType::Try) {
+            self.try_statement(parser_state);
         } else if self.match_(TokenType::LeftBrace) {
             self.begin_scope(parser_state);
             self.block(parser_state);
@@ -537,8 +971,23 @@ impl Parser {
     }
 
     fn number(&mut self, can_assign: bool, parser_state: &mut ParserState) {
-        let value = Value::from(self.previous.lexeme.parse::<f64>().unwrap());
-        self.emit_constant(value, parser_state.chunk);
+        // The scanner has already validated the literal
+        let lexeme = self.previous.lexeme.replace('_', "");
+        let value = match lexeme.get(..2) {
+            Some("0x" | "0X") => i64::from_str_radix(&lexeme[2..], 16).map(Value::from).ok(),
+            Some("0o" | "0O") => i64::from_str_radix(&lexeme[2..], 8).map(Value::from).ok(),
+            Some("0b" | "0B") => i64::from_str_radix(&lexeme[2..], 2).map(Value::from).ok(),
+            _ if lexeme.contains(['.', 'e', 'E']) => lexeme.parse::<f64>().map(Value::from).ok(),
+            _ => lexeme.parse::<i64>().map(Value::from).ok(),
+        };
+        self.expr_type = match value {
+            Some(Value::Int(_)) => StaticType::Int,
+            _ => StaticType::Float,
+        };
+        match value {
+            Some(value) => self.emit_constant(value, parser_state.chunk),
+            None => self.error("Integer literal is too large."),
+        }
     }
 
     fn or_(&mut self, can_assign: bool, parser_state: &mut ParserState) {
@@ -550,6 +999,7 @@ impl Parser {
 
         self.parse_precedence(Precedence::Or, parser_state);
         self.patch_jump(end_jump, parser_state);
+        self.expr_type = StaticType::Any;
     }
 
     fn string(&mut self, can_assign: bool, parser_state: &mut ParserState) {
@@ -557,31 +1007,555 @@ impl Parser {
         let string = &self.previous.lexeme[1..str_len - 1];
         let value = Value::from(Obj::from(string.to_string()));
         self.emit_constant(value, parser_state.chunk);
+        self.expr_type = StaticType::String;
     }
 
-    fn named_variable(&mut self, name: String, can_assign: bool, parser_state: &mut ParserState) {
-        let mut arg = self.resolve_local(&name, &parser_state.current);
+    fn interpolation(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        // "a${x}b${y}c" compiles to "a" + str(x) + "b" + str(y) + "c"
+        self.interpolation_segment(parser_state);
+        loop {
+            parser_state.current.stack_height += 1;
+            self.expression(parser_state);
+            parser_state.current.stack_height -= 1;
+            self.emit_bytes(OpCode::OpStringify.into(), OpCode::OpAdd.into(), parser_state.chunk);
+            if !self.match_(TokenType::Interpolation) {
+                break;
+            }
+            self.interpolation_segment(parser_state);
+            self.emit_byte(OpCode::OpAdd.into(), parser_state.chunk);
+        }
 
-        let (get_op, set_op): (u8, u8) = if arg != u8::MAX {
-            (OpCode::OpGetLocal.into(), OpCode::OpSetLocal.into())
-        } else {
-            arg = self.identifier_constant(name, parser_state);
-            (OpCode::OpGetGlobal.into(), OpCode::OpSetGlobal.into())
-        };
+        self.consume(TokenType::String, "Expect '}' after interpolated expression.");
+        if self.previous.token_type == TokenType::String {
+            self.string(can_assign, parser_state);
+            self.emit_byte(OpCode::OpAdd.into(), parser_state.chunk);
+        }
+        self.expr_type = StaticType::String;
+    }
+
+    fn interpolation_segment(&mut self, parser_state: &mut ParserState) {
+        // Strip the leading `"` or `}` and the trailing `${`
+        let str_len = self.previous.lexeme.len();
+        let string = &self.previous.lexeme[1..str_len - 2];
+        let value = Value::from(Obj::from(string.to_string()));
+        self.emit_constant(value, parser_state.chunk);
+    }
+
+    fn named_variable(&mut self, name: String, can_assign: bool, parser_state: &mut ParserState) {
+        let (get_op, set_op, arg, is_const) = self.resolve_variable(&name, parser_state);
         
         if can_assign && self.match_(TokenType::Equal) {
+            if is_const {
+                self.error(&format!("Can't assign to constant '{}'.", name));
+            }
             self.expression(parser_state);
+            self.check_type(&name, self.variable_type(&name, parser_state));
             self.emit_bytes(set_op, arg, parser_state.chunk);
         } else {
             self.emit_bytes(get_op, arg, parser_state.chunk);
+            self.expr_type = self.variable_type(&name, parser_state);
+        }
+    }
+
+    // Returns the get and set opcodes for the variable, their operand and
+    // whether the variable is a constant
+    fn resolve_variable(&mut self, name: &str, parser_state: &mut ParserState) -> (u8, u8, u8, bool) {
+        let index = self.resolve_local(name, &parser_state.current);
+        if index != u8::MAX {
+            let local = &parser_state.current.locals[index as usize];
+            (OpCode::OpGetLocal.into(), OpCode::OpSetLocal.into(), local.slot, local.is_const)
+        } else {
+            let is_const = parser_state.const_globals.contains(name);
+            let arg = self.identifier_constant(name.to_string(), parser_state);
+            (OpCode::OpGetGlobal.into(), OpCode::OpSetGlobal.into(), arg, is_const)
         }
     }
 
     fn variable(&mut self, can_assign: bool, parser_state: &mut ParserState) {
-        let name = self.previous.lexeme.clone();
+        let name = self.previous.lexeme.to_string();
         self.named_variable(name, can_assign, parser_state);
     }
 
+    fn call(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        let arg_count = self.argument_list(parser_state);
+        self.emit_bytes(OpCode::OpCall.into(), arg_count, parser_state.chunk);
+        self.expr_type = StaticType::Any;
+    }
+
+    fn argument_list(&mut self, parser_state: &mut ParserState) -> u8 {
+        // The callee and the arguments before each argument are on the stack
+        let stack_height = parser_state.current.stack_height;
+        parser_state.current.stack_height += 1;
+        let mut arg_count: usize = 0;
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.expression(parser_state);
+                if arg_count == 255 {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                arg_count += 1;
+                parser_state.current.stack_height += 1;
+                if !self.match_(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        parser_state.current.stack_height = stack_height;
+        self.consume(TokenType::RightParen, "Expect ')' after arguments.");
+        arg_count as u8
+    }
+
+    // var x = { var a = 1; a + 1 };
+    //
+    // The value of the block is its last expression, when it isn't followed
+    // by a `;`, and nil otherwise. It's stored in a hidden local pushed
+    // before the block's own locals, which are popped from beneath it.
+    // An `if` coming last is the value too, see `block_if`. A nested `{`
+    // is still a block statement.
+    fn block_expression(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        self.begin_scope(parser_state);
+        let result_slot = parser_state.current.stack_height as u8;
+        self.emit_byte(OpCode::OpNil.into(), parser_state.chunk);
+        self.add_hidden_local(parser_state);
+        self.begin_scope(parser_state);
+
+        let mut result_type = StaticType::Nil;
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            let is_statement = matches!(
+                self.current.token_type,
+                TokenType::Var
+                    | TokenType::Const
+                    | TokenType::Enum
+                    | TokenType::Export
+                    | TokenType::Import
+                    | TokenType::Print
+                    | TokenType::For
+                    | TokenType::While
+                    | TokenType::Throw
+                    | TokenType::Try
+                    | TokenType::LeftBrace
+            );
+            if is_statement {
+                self.declaration(parser_state);
+                continue;
+            }
+            if self.match_(TokenType::If) {
+                self.block_if(parser_state);
+                if !self.check(TokenType::RightBrace) {
+                    self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+                    continue;
+                }
+            } else {
+                if !self.statement_expression(parser_state) {
+                    continue;
+                }
+                if self.match_(TokenType::Semicolon) {
+                    self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+                    continue;
+                }
+            }
+            result_type = self.expr_type;
+            self.emit_bytes(OpCode::OpSetLocal.into(), result_slot, parser_state.chunk);
+            self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+            break;
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.");
+        self.end_scope(parser_state);
+
+        parser_state.current.locals.pop();
+        parser_state.current.scope_depth -= 1;
+        parser_state.current.stack_height -= 1;
+        self.expr_type = result_type;
+    }
+
+    // An `if` among the statements of a block expression, which can also be
+    // its value: `{ var t = f(); if (t) 1 else 2 }`. Unlike `if_expression`
+    // the branches can be statements, and the `else` is optional. Every
+    // branch leaves a value, which is nil for a statement, an expression
+    // followed by a `;` or a missing `else`.
+    fn block_if(&mut self, parser_state: &mut ParserState) {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        self.expression(parser_state);
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+
+        let then_jump = self.emit_jump(OpCode::OpJumpIfFalse.into(), parser_state);
+        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        self.if_branch(parser_state);
+        let then_type = self.expr_type;
+        let else_jump = self.emit_jump(OpCode::OpJump.into(), parser_state);
+
+        self.patch_jump(then_jump, parser_state);
+        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        if self.match_(TokenType::Else) {
+            self.if_branch(parser_state);
+        } else {
+            self.emit_byte(OpCode::OpNil.into(), parser_state.chunk);
+            self.expr_type = StaticType::Nil;
+        }
+        self.patch_jump(else_jump, parser_state);
+
+        if self.expr_type != then_type {
+            self.expr_type = StaticType::Any;
+        }
+    }
+
+    fn if_branch(&mut self, parser_state: &mut ParserState) {
+        if self.match_(TokenType::If) {
+            self.block_if(parser_state);
+            return;
+        }
+        if self.match_(TokenType::LeftBrace) {
+            self.block_expression(false, parser_state);
+            return;
+        }
+
+        let is_statement = matches!(
+            self.current.token_type,
+            TokenType::Print | TokenType::For | TokenType::While | TokenType::Throw | TokenType::Try
+        );
+        if is_statement {
+            self.statement(parser_state);
+        } else if self.statement_expression(parser_state) {
+            if !self.match_(TokenType::Semicolon) {
+                return;
+            }
+            self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        }
+        self.emit_byte(OpCode::OpNil.into(), parser_state.chunk);
+        self.expr_type = StaticType::Nil;
+    }
+
+    // if (condition) a else b
+    //
+    // Only reached in expression position, a statement starting with `if`
+    // is an if statement, or a `block_if` in a block expression. The `else` is required so there is always a value.
+    fn if_expression(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        self.expression(parser_state);
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+
+        let then_jump = self.emit_jump(OpCode::OpJumpIfFalse.into(), parser_state);
+        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        self.expression(parser_state);
+        let then_type = self.expr_type;
+        let else_jump = self.emit_jump(OpCode::OpJump.into(), parser_state);
+
+        self.patch_jump(then_jump, parser_state);
+        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+        self.consume(TokenType::Else, "Expect 'else' in if expression.");
+        self.expression(parser_state);
+        self.patch_jump(else_jump, parser_state);
+
+        if self.expr_type != then_type {
+            self.expr_type = StaticType::Any;
+        }
+    }
+
+    // match value { pattern if guard => result, ... }
+    //
+    // The arms are tried top to bottom. The value being matched lives in a
+    // hidden local so patterns, guards and arms can read it, and the result
+    // of the matching arm is stored over it. Every failed test leaves a
+    // `false` on the stack and jumps to the arm's cleanup, which pops it
+    // and moves on to the next arm.
+    fn pattern_match(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        self.begin_scope(parser_state);
+        let subject_slot = parser_state.current.stack_height as u8;
+        self.expression(parser_state);
+        self.add_hidden_local(parser_state);
+        self.consume(TokenType::LeftBrace, "Expect '{' after match value.");
+
+        let mut end_jumps = vec![];
+        let mut exhaustive = false;
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            if exhaustive {
+                self.warning_at_current("Unreachable match arm.");
+            }
+            self.begin_scope(parser_state);
+
+            let mut path = vec![];
+            let mut bindings = vec![];
+            let mut fail_jumps = vec![];
+            self.pattern(subject_slot, &mut path, &mut bindings, &mut fail_jumps, parser_state);
+
+            let binding_count = bindings.len();
+            for (name, path) in bindings {
+                self.emit_pattern_load(subject_slot, &path, parser_state);
+                self.add_hidden_local(parser_state);
+                if let Some(local) = parser_state.current.locals.last_mut() {
+                    local.name = name;
+                }
+            }
+
+            let mut guard_jump = None;
+            if self.match_(TokenType::If) {
+                self.expression(parser_state);
+                guard_jump = Some(self.emit_jump(OpCode::OpJumpIfFalse.into(), parser_state));
+                self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+            }
+
+            self.consume(TokenType::EqualGreater, "Expect '=>' after match pattern.");
+            self.expression(parser_state);
+            self.emit_bytes(OpCode::OpSetLocal.into(), subject_slot, parser_state.chunk);
+            self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+            self.end_scope(parser_state);
+            end_jumps.push(self.emit_jump(OpCode::OpJump.into(), parser_state));
+
+            let mut next_arm_jump = None;
+            if let Some(guard_jump) = guard_jump {
+                // Pop the guard's `false` and the bindings
+                self.patch_jump(guard_jump, parser_state);
+                for _ in 0..=binding_count {
+                    self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+                }
+                next_arm_jump = Some(self.emit_jump(OpCode::OpJump.into(), parser_state));
+            }
+            if !fail_jumps.is_empty() {
+                for jump in fail_jumps.iter() {
+                    self.patch_jump(*jump, parser_state);
+                }
+                self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+            }
+            if let Some(jump) = next_arm_jump {
+                self.patch_jump(jump, parser_state);
+            }
+
+            if fail_jumps.is_empty() && guard_jump.is_none() {
+                exhaustive = true;
+            }
+            if !self.match_(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after match arms.");
+
+        if !exhaustive {
+            self.emit_byte(OpCode::OpNoMatch.into(), parser_state.chunk);
+        }
+        for jump in end_jumps {
+            self.patch_jump(jump, parser_state);
+        }
+
+        // The hidden local now holds the result, which is left on the stack
+        // as the value of the match expression
+        parser_state.current.locals.pop();
+        parser_state.current.scope_depth -= 1;
+        parser_state.current.stack_height -= 1;
+        self.expr_type = StaticType::Any;
+    }
+
+    // Compiles the tests for a single pattern against the value found by
+    // following `path` (variant field indexes) from the matched value.
+    // Bindings are only collected here, they are pushed once every test passed.
+    fn pattern(
+        &mut self,
+        subject_slot: u8,
+        path: &mut Vec<u8>,
+        bindings: &mut Vec<(String, Vec<u8>)>,
+        fail_jumps: &mut Vec<usize>,
+        parser_state: &mut ParserState,
+    ) {
+        if self.match_(TokenType::Identifier) {
+            let name = self.previous.lexeme.to_string();
+            if self.check(TokenType::Dot) {
+                self.enum_pattern(name, subject_slot, path, bindings, fail_jumps, parser_state);
+            } else if name != "_" {
+                if bindings.iter().any(|(bound, _)| bound == &name) {
+                    self.error("Already a binding with this name in this pattern.");
+                }
+                bindings.push((name, path.clone()));
+            }
+            return;
+        }
+
+        self.emit_pattern_load(subject_slot, path, parser_state);
+        if self.match_(TokenType::Minus) {
+            self.consume(TokenType::Number, "Expect number after '-' in pattern.");
+            self.number(false, parser_state);
+            self.emit_byte(OpCode::OpNegate.into(), parser_state.chunk);
+        } else if self.match_(TokenType::Number) {
+            self.number(false, parser_state);
+        } else if self.match_(TokenType::String) {
+            self.string(false, parser_state);
+        } else if self.match_(TokenType::True)
+            || self.match_(TokenType::False)
+            || self.match_(TokenType::Nil)
+        {
+            self.literal(false, parser_state);
+        } else {
+            self.error_at_current("Expect pattern.");
+            return;
+        }
+        self.emit_byte(OpCode::OpEqual.into(), parser_state.chunk);
+        fail_jumps.push(self.emit_jump(OpCode::OpJumpIfFalse.into(), parser_state));
+        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+    }
+
+    // Shape.Circle, Shape.Rect(w, _) or module.Shape.Circle(r)
+    fn enum_pattern(
+        &mut self,
+        name: String,
+        subject_slot: u8,
+        path: &mut Vec<u8>,
+        bindings: &mut Vec<(String, Vec<u8>)>,
+        fail_jumps: &mut Vec<usize>,
+        parser_state: &mut ParserState,
+    ) {
+        self.emit_pattern_load(subject_slot, path, parser_state);
+        self.named_variable(name, false, parser_state);
+        while self.match_(TokenType::Dot) {
+            self.dot(false, parser_state);
+        }
+
+        // The field count is patched in once the sub-patterns are parsed,
+        // u8::MAX means there were no parentheses
+        self.emit_bytes(OpCode::OpIsVariant.into(), u8::MAX, parser_state.chunk);
+        let field_count_offset = parser_state.chunk.len() - 1;
+        fail_jumps.push(self.emit_jump(OpCode::OpJumpIfFalse.into(), parser_state));
+        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+
+        if self.match_(TokenType::LeftParen) {
+            let mut field_count: u8 = 0;
+            if !self.check(TokenType::RightParen) {
+                loop {
+                    if field_count == u8::MAX - 1 {
+                        self.error("Too many fields in pattern.");
+                    }
+                    path.push(field_count);
+                    self.pattern(subject_slot, path, bindings, fail_jumps, parser_state);
+                    path.pop();
+                    field_count = field_count.saturating_add(1);
+                    if !self.match_(TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightParen, "Expect ')' after pattern fields.");
+            parser_state.chunk.code[field_count_offset] = field_count;
+        }
+    }
+
+    fn emit_pattern_load(&mut self, subject_slot: u8, path: &[u8], parser_state: &mut ParserState) {
+        self.emit_bytes(OpCode::OpGetLocal.into(), subject_slot, parser_state.chunk);
+        for field in path {
+            self.emit_bytes(OpCode::OpGetVariantField.into(), *field, parser_state.chunk);
+        }
+    }
+
+    // A local for a value the compiler put on the stack, it has no name
+    // so lox code can't refer to it
+    fn add_hidden_local(&mut self, parser_state: &mut ParserState) {
+        let depth = parser_state.current.scope_depth;
+        if !parser_state.current.add_local(Local::new(String::new(), depth)) {
+            self.error("Too many local variables defined.");
+        }
+        parser_state.current.stack_height += 1;
+    }
+
+    fn dot(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        self.consume(TokenType::Identifier, "Expect property name after '.'.");
+        let name = self.identifier_constant(self.previous.lexeme.to_string(), parser_state);
+        self.emit_bytes(OpCode::OpGetProperty.into(), name, parser_state.chunk);
+        self.expr_type = StaticType::Any;
+    }
+
+    // string[index], string[start..end]
+    fn index(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        let target_type = self.expr_type;
+        parser_state.current.stack_height += 1;
+        self.expression(parser_state);
+        parser_state.current.stack_height -= 1;
+        self.consume(TokenType::RightBracket, "Expect ']' after index.");
+        self.emit_byte(OpCode::OpIndex.into(), parser_state.chunk);
+        self.expr_type = match target_type {
+            StaticType::String => StaticType::String,
+            _ => StaticType::Any,
+        };
+    }
+
+    // start..end and start..=end. The end can be left out, as in `s[2..]`,
+    // when the range isn't followed by something that starts an expression.
+    // A `{` after a range is taken to be the start of a block, like the body
+    // of a `match`, rather than a block expression.
+    fn range(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        let inclusive = self.previous.token_type == TokenType::DotDotEqual;
+        let has_end = ParseRule::get_rule(&self.current.token_type).prefix.is_some()
+            && !matches!(
+                self.current.token_type,
+                TokenType::LeftBrace | TokenType::DotDot | TokenType::DotDotEqual
+            );
+        if has_end {
+            parser_state.current.stack_height += 1;
+            self.parse_precedence(Precedence::Range.greater(), parser_state);
+            parser_state.current.stack_height -= 1;
+        } else {
+            if inclusive {
+                self.error_at_current("Expect end of inclusive range.");
+            }
+            self.emit_byte(OpCode::OpNil.into(), parser_state.chunk);
+        }
+        if self.check(TokenType::DotDot) || self.check(TokenType::DotDotEqual) {
+            self.error_at_current("Can't chain ranges.");
+        }
+        self.emit_bytes(OpCode::OpRange.into(), inclusive as u8, parser_state.chunk);
+        self.expr_type = StaticType::Any;
+    }
+
+    // ..end, with no start
+    fn open_range(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        self.emit_byte(OpCode::OpNil.into(), parser_state.chunk);
+        self.range(can_assign, parser_state);
+    }
+
+    // record?.field
+    //
+    // A nil record skips the rest of the call chain, so `a?.b.c(1)` is nil
+    // when `a` is, instead of failing on `.c`.
+    fn optional_dot(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        let nil_jump = self.emit_jump(OpCode::OpJumpIfNil.into(), parser_state);
+        if self.match_(TokenType::LeftBracket) {
+            self.index(false, parser_state);
+        } else {
+            self.dot(false, parser_state);
+        }
+        self.parse_infix(Precedence::Call, false, parser_state);
+        self.patch_jump(nil_jump, parser_state);
+        self.expr_type = StaticType::Any;
+    }
+
+    // value ?? fallback
+    fn coalesce(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        let else_jump = self.emit_jump(OpCode::OpJumpIfNil.into(), parser_state);
+        let end_jump = self.emit_jump(OpCode::OpJump.into(), parser_state);
+
+        self.patch_jump(else_jump, parser_state);
+        self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
+
+        self.parse_precedence(Precedence::Or, parser_state);
+        self.patch_jump(end_jump, parser_state);
+        self.expr_type = StaticType::Any;
+    }
+
+    // value is String, value is Shape, value is Shape.Circle
+    //
+    // Built-in type names are encoded in the operand. Anything else is an
+    // enum or a variant that is evaluated and checked at runtime.
+    fn is_(&mut self, can_assign: bool, parser_state: &mut ParserState) {
+        self.consume(TokenType::Identifier, "Expect type name after 'is'.");
+        let type_name = self.previous.lexeme.to_string();
+        match TYPE_NAMES.iter().position(|name| *name == type_name) {
+            Some(index) => self.emit_bytes(OpCode::OpIs.into(), index as u8, parser_state.chunk),
+            None => {
+                parser_state.current.stack_height += 1;
+                self.variable(false, parser_state);
+                self.parse_infix(Precedence::Call, false, parser_state);
+                parser_state.current.stack_height -= 1;
+                self.emit_bytes(OpCode::OpIs.into(), u8::MAX, parser_state.chunk);
+            }
+        }
+        self.expr_type = StaticType::Bool;
+    }
+
     fn grouping(&mut self, can_assign: bool, parser_state: &mut ParserState) {
         self.expression(parser_state);
         self.consume(TokenType::RightParen, "Expect ')' after expression.");
@@ -591,8 +1565,14 @@ impl Parser {
         let operator_type = self.previous.token_type.clone();
         self.parse_precedence(Precedence::Unary, parser_state);
         match operator_type {
-            TokenType::Minus => self.emit_byte(OpCode::OpNegate.into(), parser_state.chunk),
-            TokenType::Bang => self.emit_byte(OpCode::OpNot.into(), parser_state.chunk),
+            TokenType::Minus => {
+                self.emit_byte(OpCode::OpNegate.into(), parser_state.chunk);
+                self.expr_type = self.expr_type.negate();
+            }
+            TokenType::Bang => {
+                self.emit_byte(OpCode::OpNot.into(), parser_state.chunk);
+                self.expr_type = StaticType::Bool;
+            }
             _ => {}
         };
     }
@@ -610,7 +1590,11 @@ impl Parser {
 
         let can_assign = precedence <= Precedence::Assignment;
         prefix_rule(self, can_assign, parser_state);
+        self.parse_infix(precedence, can_assign, parser_state);
+    }
 
+    // The rest of `parse_precedence` once the prefix expression is compiled
+    fn parse_infix(&mut self, precedence: Precedence, can_assign: bool, parser_state: &mut ParserState) {
         while precedence <= ParseRule::get_rule(&self.current.token_type).precedence {
             self.advance();
             let infix_rule = ParseRule::get_rule(&self.previous.token_type)
@@ -632,7 +1616,7 @@ impl Parser {
             return 0;
         }
 
-        let token_name = (&self.previous.lexeme).to_string();
+        let token_name = self.previous.lexeme.to_string();
         let constant_index = self.identifier_constant(token_name, parser_state);
         return constant_index;
     }
@@ -640,6 +1624,8 @@ impl Parser {
     fn mark_initialized(&self, parser_state: &mut ParserState) {
         let local_count = parser_state.current.locals.len();
         parser_state.current.locals[local_count - 1].depth = parser_state.current.scope_depth;
+        // Its initializer has been pushed
+        parser_state.current.stack_height += 1;
     }
 
     fn define_variable(&mut self, global: u8, parser_state: &mut ParserState) {
@@ -650,6 +1636,47 @@ impl Parser {
         self.emit_bytes(OpCode::OpDefineGlobal.into(), global, parser_state.chunk);
     }
 
+    // Parses the optional `: Type` after the name of a variable being
+    // declared and records it for the variable
+    fn type_annotation(&mut self, name: String, parser_state: &mut ParserState) -> StaticType {
+        let mut annotation = StaticType::Any;
+        if self.match_(TokenType::Colon) {
+            self.consume(TokenType::Identifier, "Expect type name after ':'.");
+            match StaticType::from_name(&self.previous.lexeme) {
+                Some(static_type) => annotation = static_type,
+                None => self.error(&format!("Unknown type '{}'.", self.previous.lexeme)),
+            }
+        }
+
+        if parser_state.current.scope_depth > 0 {
+            if let Some(local) = parser_state.current.locals.last_mut() {
+                local.annotation = annotation;
+            }
+        } else {
+            parser_state.global_types.insert(name, annotation);
+        }
+        annotation
+    }
+
+    fn variable_type(&self, name: &str, parser_state: &ParserState) -> StaticType {
+        let local = parser_state.current.locals.iter().rev().find(|local| local.name == name);
+        match local {
+            Some(local) => local.annotation,
+            None => parser_state.global_types.get(name).copied().unwrap_or(StaticType::Any),
+        }
+    }
+
+    // Checks the expression that was just compiled can be stored in a
+    // variable of the given type
+    fn check_type(&mut self, name: &str, annotation: StaticType) {
+        if self.typecheck && !annotation.accepts(self.expr_type) {
+            self.error(&format!(
+                "Type mismatch: '{}' is declared as {} but got {}.",
+                name, annotation, self.expr_type
+            ));
+        }
+    }
+
     fn and_(&mut self, can_assign: bool, parser_state: &mut ParserState) {
         let end_jump = self.emit_jump(OpCode::OpJumpIfFalse.into(), parser_state);
 
@@ -657,6 +1684,7 @@ impl Parser {
         self.parse_precedence(Precedence::And, parser_state);
 
         self.patch_jump(end_jump, parser_state);
+        self.expr_type = StaticType::Any;
     }
 
     fn identifier_constant(&mut self, name: String, parser_state: &mut ParserState) -> u8 {
@@ -687,10 +1715,13 @@ impl Parser {
             self.error("Too many local variables defined.");
             return;
         }
-        let local_name = self.previous.lexeme.clone();
+        let local_name = self.previous.lexeme.to_string();
         let could_create_local = parser_state.current.add_local(
             Local::new(local_name, u8::MAX)
         );
+        if !could_create_local {
+            self.error("Too many values on the stack.");
+        }
     }
 
     fn declare_variable(&mut self, parser_state: &mut ParserState) {
@@ -698,7 +1729,7 @@ impl Parser {
             return;
         }
 
-        let variable_name = self.previous.lexeme.clone();
+        let variable_name = self.previous.lexeme.to_string();
         for local in parser_state.current.locals.iter().rev() {
             if local.depth != u8::MAX && local.depth < parser_state.current.scope_depth {
                 break;
@@ -714,7 +1745,12 @@ impl Parser {
     fn binary(&mut self, can_assign: bool, parser_state: &mut ParserState) {
         let operator_type = self.previous.token_type.clone();
         let parse_rule = ParseRule::get_rule(&operator_type);
+        let left_type = self.expr_type;
+        // The left operand stays on the stack while the right one is compiled
+        parser_state.current.stack_height += 1;
         self.parse_precedence(parse_rule.precedence.greater(), parser_state);
+        parser_state.current.stack_height -= 1;
+        self.expr_type = StaticType::binary(&operator_type, left_type, self.expr_type);
 
         match operator_type {
             TokenType::BangEqual => {
@@ -723,12 +1759,10 @@ impl Parser {
             TokenType::EqualEqual => self.emit_byte(OpCode::OpEqual.into(), parser_state.chunk),
             TokenType::Greater => self.emit_byte(OpCode::OpGreater.into(), parser_state.chunk),
             TokenType::GreaterEqual => {
-                self.emit_bytes(OpCode::OpLess.into(), OpCode::OpNot.into(), parser_state.chunk)
+                self.emit_byte(OpCode::OpGreaterEqual.into(), parser_state.chunk)
             }
             TokenType::Less => self.emit_byte(OpCode::OpLess.into(), parser_state.chunk),
-            TokenType::LessEqual => {
-                self.emit_bytes(OpCode::OpGreater.into(), OpCode::OpNot.into(), parser_state.chunk)
-            }
+            TokenType::LessEqual => self.emit_byte(OpCode::OpLessEqual.into(), parser_state.chunk),
             TokenType::Plus => self.emit_byte(OpCode::OpAdd.into(), parser_state.chunk),
             TokenType::Minus => self.emit_byte(OpCode::OpSubtract.into(), parser_state.chunk),
             TokenType::Star => self.emit_byte(OpCode::OpMultiply.into(), parser_state.chunk),
@@ -744,6 +1778,10 @@ impl Parser {
             TokenType::False => self.emit_byte(OpCode::OpFalse.into(), parser_state.chunk),
             _ => {}
         };
+        self.expr_type = match self.previous.token_type {
+            TokenType::Nil => StaticType::Nil,
+            _ => StaticType::Bool,
+        };
     }
 
     fn begin_scope(&mut self, parser_state: &mut ParserState) {
@@ -758,6 +1796,7 @@ impl Parser {
         {
             self.emit_byte(OpCode::OpPop.into(), parser_state.chunk);
             parser_state.current.locals.pop();
+            parser_state.current.stack_height -= 1;
         }
     }
 
@@ -833,6 +1872,10 @@ impl Parser {
         self.error_at(&self.current.clone(), true, message);
     }
 
+    fn warning_at_current(&self, message: &str) {
+        eprintln!("[line {}] Warning at '{}' {}", self.current.line, self.current.lexeme, message);
+    }
+
     fn error_at(&mut self, token: &Token, is_error: bool, message: &str) {
         if self.panic_mode {
             return;
@@ -32,20 +32,39 @@ pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> usize {
             OC::OpSetLocal => byte_instruction("OpSetLocal", chunk, offset),
             OC::OpGetGlobal => constant_instruction("OpGetGlobal", chunk, offset),
             OC::OpDefineGlobal => constant_instruction("OpDefineGlobal", chunk, offset),
+            OC::OpDefineConstGlobal => constant_instruction("OpDefineConstGlobal", chunk, offset),
             OC::OpSetGlobal => constant_instruction("OpSetGlobal", chunk, offset),
+            OC::OpGetProperty => constant_instruction("OpGetProperty", chunk, offset),
+            OC::OpIndex => simple_instruction("OpIndex", offset),
+            OC::OpImport => constant_instruction("OpImport", chunk, offset),
+            OC::OpExport => constant_instruction("OpExport", chunk, offset),
             OC::OpEqual => simple_instruction("OpEqual", offset),
             OC::OpGreater => simple_instruction("OpGreater", offset),
+            OC::OpGreaterEqual => simple_instruction("OpGreaterEqual", offset),
             OC::OpLess => simple_instruction("OpLess", offset),
+            OC::OpLessEqual => simple_instruction("OpLessEqual", offset),
             OC::OpAdd => simple_instruction("OpAdd", offset),
             OC::OpSubtract => simple_instruction("OpSubtract", offset),
             OC::OpMultiply => simple_instruction("OpMultiply", offset),
             OC::OpDivide => simple_instruction("OpDivide", offset),
+            OC::OpRange => byte_instruction("OpRange", chunk, offset),
             OC::OpNot => simple_instruction("OpNot", offset),
             OC::OpNegate => simple_instruction("OpNegate", offset),
             OC::OpPrint => simple_instruction("OpPrint", offset),
+            OC::OpStringify => simple_instruction("OpStringify", offset),
             OC::OpJump => jump_instruction("OpJump", 1, chunk, offset),
             OC::OpJumpIfFalse => jump_instruction("OpJumpIfFalse", 1, chunk, offset),
+            OC::OpJumpIfNil => jump_instruction("OpJumpIfNil", 1, chunk, offset),
             OC::OpLoop => jump_instruction("OpLoop", -1, chunk, offset),
+            OC::OpCall => byte_instruction("OpCall", chunk, offset),
+            OC::OpIsVariant => byte_instruction("OpIsVariant", chunk, offset),
+            OC::OpIs => byte_instruction("OpIs", chunk, offset),
+            OC::OpGetVariantField => byte_instruction("OpGetVariantField", chunk, offset),
+            OC::OpNoMatch => simple_instruction("OpNoMatch", offset),
+            OC::OpTry => jump_instruction("OpTry", 1, chunk, offset),
+            OC::OpPopHandler => simple_instruction("OpPopHandler", offset),
+            OC::OpThrow => simple_instruction("OpThrow", offset),
+            OC::OpEndFinally => simple_instruction("OpEndFinally", offset),
             OC::OpReturn => simple_instruction("OpReturn", offset),
         }
     } else {
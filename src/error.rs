@@ -11,7 +11,7 @@ pub fn report(line_num: u32, whr: &str, message: &str) {
 #[derive(Debug)]
 pub enum Error {
     Scan { message: String, line: u32 },
-    Parse { message: String, token: Token },
+    Parse { message: String, token: Token<'static> },
 }
 
 pub fn report_err(error: &Error) {
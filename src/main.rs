@@ -10,6 +10,7 @@ pub mod debug;
 pub mod error;
 pub mod scanner;
 pub mod token;
+pub mod types;
 pub mod value;
 #[macro_use]
 pub mod vm;
@@ -18,26 +19,36 @@ pub mod obj;
 use std::{
     env, fs,
     io::{self, Write},
+    path::Path,
     process::ExitCode,
 };
 
 use crate::{
     chunk::{Chunk, OpCode::*},
     debug::disassemble_chunk,
-    value::VmValue,
     vm::{InterpretError, VM},
 };
 
 fn main() -> ExitCode {
     let mut vm = VM::new();
+    // Extra directories to look for imported modules in
+    if let Some(lox_path) = env::var_os("LOX_PATH") {
+        for path in env::split_paths(&lox_path) {
+            vm.add_search_path(path);
+        }
+    }
 
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--typecheck") {
+        args.remove(index);
+        vm.set_typecheck(true);
+    }
     if args.len() == 1 {
         repl(&mut vm)
     } else if args.len() == 2 {
         run_file(&mut vm, &args[1])
     } else {
-        println!("Usage: lox-rust [path]");
+        println!("Usage: lox-rust [--typecheck] [path]");
         ExitCode::from(42)
     }
 }
@@ -58,6 +69,7 @@ fn run_file(vm: &mut VM, file_path: &str) -> ExitCode {
     let result = fs::read_to_string(file_path);
     match result {
         Ok(source) => {
+            vm.set_script_path(Path::new(file_path));
             let result = vm.interpret(source);
             use InterpretError as IE;
             match result {
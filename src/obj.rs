@@ -1,13 +1,81 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
 
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::value::Value;
+
+// Natives only compare equal to themselves, which is all `==` needs
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Obj {
     Str(String),
+    // Raised by the VM for runtime errors, catchable with try/catch
+    Error { message: String, line: u32 },
+    // An imported file, holding the values of its exported globals
+    Module { name: String, exports: BTreeMap<String, Value> },
+    // An enum declaration, with the field names of each variant
+    Enum { name: String, variants: Vec<(String, Vec<String>)> },
+    // A variant of an enum. A variant with fields but no values is the
+    // constructor you get from `Shape.Circle`, calling it fills the values.
+    Variant { enum_name: String, name: String, fields: Vec<String>, values: Vec<Value> },
+    // `start..end` or `start..=end`, either bound can be left out. Only used
+    // for slicing so far, it doesn't hold the values in between.
+    Range { start: Option<i64>, end: Option<i64>, inclusive: bool },
+    // A function implemented in Rust
+    Native { name: String, arity: usize, function: NativeFn },
+}
+
+// An `Err` is thrown as a runtime error with that message
+pub type NativeFn = fn(&[Value]) -> Result<Value, String>;
+
+impl Obj {
+    pub fn get_property(&self, name: &str) -> Option<Value> {
+        match (self, name) {
+            // Counted in graphemes, like indexes into the string
+            (Self::Str(string), "length") => Some(Value::from(string.graphemes(true).count() as i64)),
+            (Self::Error { message, .. }, "message") => {
+                Some(Value::from(Obj::from(message.clone())))
+            }
+            (Self::Error { line, .. }, "line") => Some(Value::from(*line as i64)),
+            (Self::Module { exports, .. }, name) => exports.get(name).cloned(),
+            (Self::Enum { name: enum_name, variants }, name) => {
+                let (name, fields) = variants.iter().find(|(variant, _)| variant == name)?;
+                Some(Value::from(Obj::Variant {
+                    enum_name: enum_name.clone(),
+                    name: name.clone(),
+                    fields: fields.clone(),
+                    values: vec![],
+                }))
+            }
+            // `shape.variant == Shape.Circle` tests which variant a value is
+            (Self::Variant { enum_name, name, fields, .. }, "variant") => {
+                Some(Value::from(Obj::Variant {
+                    enum_name: enum_name.clone(),
+                    name: name.clone(),
+                    fields: fields.clone(),
+                    values: vec![],
+                }))
+            }
+            (Self::Range { start, .. }, "start") => Some(start.map(Value::from).unwrap_or(Value::Nil)),
+            (Self::Range { end, .. }, "end") => Some(end.map(Value::from).unwrap_or(Value::Nil)),
+            (Self::Variant { fields, values, .. }, name) => {
+                let index = fields.iter().position(|field| field == name)?;
+                values.get(index).cloned()
+            }
+            _ => None,
+        }
+    }
 }
 
+// Strings are kept in NFC, like identifiers, so `é` written precomposed or
+// as `e` and a combining accent compare equal however the string was built
 impl From<String> for Obj {
     fn from(value: String) -> Self {
-        Obj::Str(value)
+        match is_nfc_quick(value.chars()) {
+            IsNormalized::Yes => Obj::Str(value),
+            _ => Obj::Str(value.nfc().collect()),
+        }
     }
 }
 
@@ -15,6 +83,35 @@ impl Display for Obj {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Str(o) => write!(f, "{}", &o),
+            Self::Error { message, .. } => write!(f, "Error: {}", message),
+            Self::Module { name, .. } => write!(f, "<module {}>", name),
+            Self::Enum { name, .. } => write!(f, "<enum {}>", name),
+            // A constructor shows its field names so it can't be mistaken
+            // for a unit variant
+            Self::Variant { enum_name, name, fields, values }
+                if values.is_empty() && !fields.is_empty() =>
+            {
+                write!(f, "<constructor {}.{}({})>", enum_name, name, fields.join(", "))
+            }
+            Self::Variant { enum_name, name, values, .. } => {
+                write!(f, "{}.{}", enum_name, name)?;
+                if !values.is_empty() {
+                    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                    write!(f, "({})", values.join(", "))?;
+                }
+                Ok(())
+            }
+            Self::Range { start, end, inclusive } => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "{}", if *inclusive { "..=" } else { ".." })?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+            Self::Native { name, .. } => write!(f, "<native fn {}>", name),
         }
     }
 }
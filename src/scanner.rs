@@ -3,36 +3,45 @@ use crate::{
     token::{Token, TokenType},
     value::Value,
 };
-use std::iter::Iterator;
+use std::{borrow::Cow, iter::Iterator};
+use unicode_ident::{is_xid_continue, is_xid_start};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
 
 pub struct ScanError {
     pub message: String,
     pub line: u32,
 }
 
-pub struct Scanner {
-    source: Vec<char>,
+// Tokens borrow their lexemes from the source, `start` and `current` are
+// byte offsets into it.
+pub struct Scanner<'a> {
+    source: &'a str,
     start: usize,
     current: usize,
     line: u32,
+    // One entry per open `${` in a string, counting the braces
+    // nested inside the interpolated expression.
+    interpolations: Vec<u32>,
 }
 
-impl Scanner {
-    pub fn new(source: String) -> Self {
+impl<'a> Scanner<'a> {
+    pub fn new(source: &'a str) -> Self {
         //println!("Created scanner with source: {}", source);
         Self {
-            source: source.chars().collect(),
+            source,
             start: 0,
             current: 0,
             line: 1,
+            interpolations: vec![],
         }
     }
 
-    pub fn reset_with_source(&mut self, source: String) {
-        self.source = source.chars().collect();
+    pub fn reset_with_source(&mut self, source: &'a str) {
+        self.source = source;
         self.start = 0;
         self.current = 0;
         self.line = 1;
+        self.interpolations.clear();
     }
 
     /*
@@ -56,19 +65,54 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
-    pub fn scan_token(&mut self) -> Result<Token, ScanError> {
+    pub fn scan_token(&mut self) -> Result<Token<'a>, ScanError> {
+        self.skip_whitespace()?;
         self.start = self.current;
         if self.is_at_end() {
+            if !self.interpolations.is_empty() {
+                self.interpolations.clear();
+                return Result::Err(ScanError {
+                    message: "Unterminated string interpolation".to_string(),
+                    line: self.line,
+                });
+            }
             return self.build_token(TokenType::Eof);
         }
         let character = self.advance();
         match character {
             '(' => self.build_token(TokenType::LeftParen),
             ')' => self.build_token(TokenType::RightParen),
-            '{' => self.build_token(TokenType::LeftBrace),
-            '}' => self.build_token(TokenType::RightBrace),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.build_token(TokenType::LeftBrace)
+            }
+            '}' => match self.interpolations.last_mut() {
+                // This brace closes a `${`, so the string continues
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.string()
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.build_token(TokenType::RightBrace)
+                }
+                None => self.build_token(TokenType::RightBrace),
+            },
+            '[' => self.build_token(TokenType::LeftBracket),
+            ']' => self.build_token(TokenType::RightBracket),
+            ':' => self.build_token(TokenType::Colon),
             ',' => self.build_token(TokenType::Comma),
-            '.' => self.build_token(TokenType::Dot),
+            '.' => {
+                if !self.match_('.') {
+                    self.build_token(TokenType::Dot)
+                } else if self.match_('=') {
+                    self.build_token(TokenType::DotDotEqual)
+                } else {
+                    self.build_token(TokenType::DotDot)
+                }
+            }
             ';' => self.build_token(TokenType::Semicolon),
             '-' => self.build_token(TokenType::Minus),
             '+' => self.build_token(TokenType::Plus),
@@ -83,6 +127,8 @@ impl Scanner {
             '=' => {
                 if self.match_('=') {
                     self.build_token(TokenType::EqualEqual)
+                } else if self.match_('>') {
+                    self.build_token(TokenType::EqualGreater)
                 } else {
                     self.build_token(TokenType::Equal)
                 }
@@ -101,33 +147,24 @@ impl Scanner {
                     self.build_token(TokenType::Greater)
                 }
             }
-            '/' => {
-                if self.match_('/') {
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
-                    self.proceed_with_next()
-                } else if self.match_('*') {
-                    let result = self.multiline_comment();
-                    if result.is_err() {
-                        Result::Err(result.unwrap_err())
-                    } else {
-                        self.proceed_with_next()
-                    }
+            '?' => {
+                if self.match_('?') {
+                    self.build_token(TokenType::QuestionQuestion)
+                } else if self.match_('.') {
+                    self.build_token(TokenType::QuestionDot)
                 } else {
-                    self.build_token(TokenType::Slash)
+                    Result::Err(ScanError {
+                        message: "Unexpected character: ?".to_string(),
+                        line: self.line,
+                    })
                 }
             }
-            ' ' | '\r' | '\t' => self.proceed_with_next(),
-            '\n' => {
-                self.line += 1;
-                self.proceed_with_next()
-            }
+            '/' => self.build_token(TokenType::Slash),
             '"' => self.string(),
             _ => {
                 if character.is_digit(10) {
                     self.number()
-                } else if character.is_alphabetic() {
+                } else if is_xid_start(character) || character == '_' {
                     self.identifier_or_keyword()
                 } else {
                     Result::Err(ScanError {
@@ -139,45 +176,67 @@ impl Scanner {
         }
     }
 
-    fn proceed_with_next(&mut self) -> Result<Token, ScanError> {
-        self.scan_token()
+    // Skips whitespace and comments in a loop, so long runs of them in
+    // generated scripts don't grow the call stack
+    fn skip_whitespace(&mut self) -> Result<(), ScanError> {
+        loop {
+            match self.peek() {
+                ' ' | '\r' | '\t' => {
+                    self.advance();
+                }
+                '\n' => {
+                    self.line += 1;
+                    self.advance();
+                }
+                '/' if self.peek_next() == '/' => {
+                    while self.peek() != '\n' && !self.is_at_end() {
+                        self.advance();
+                    }
+                }
+                '/' if self.peek_next() == '*' => {
+                    self.advance();
+                    self.advance();
+                    self.multiline_comment()?;
+                }
+                _ => return Result::Ok(()),
+            }
+        }
     }
 
     fn match_(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            false
-        } else if self.source[self.current] != expected {
+        if self.is_at_end() || self.peek() != expected {
             false
         } else {
-            self.current += 1;
+            self.current += expected.len_utf8();
             true
         }
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.source[self.current]
-        }
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            self.source[self.current + 1]
-        }
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn advance(&mut self) -> char {
-        let c = self.source[self.current];
-        self.current += 1;
+        let c = self.peek();
+        self.current += c.len_utf8();
         c
     }
 
-    fn string(&mut self) -> Result<Token, ScanError> {
+    // Scans a string literal, or the part of one up to a `${`.
+    // An interpolated string like `"a${x}b${y}c"` is scanned as the tokens
+    // Interpolation(`"a${`), x, Interpolation(`}b${`), y, String(`}c"`)
+    fn string(&mut self) -> Result<Token<'a>, ScanError> {
         while self.peek() != '"' && !self.is_at_end() {
+            if self.peek() == '$' && self.peek_next() == '{' {
+                self.advance();
+                self.advance();
+                self.interpolations.push(0);
+                return self.build_token_value(TokenType::Interpolation);
+            }
             if self.peek() == '\n' {
                 self.line += 1;
             }
@@ -193,50 +252,125 @@ impl Scanner {
         self.build_token_value(TokenType::String)
     }
 
-    fn number(&mut self) -> Result<Token, ScanError> {
-        while self.peek().is_digit(10) {
-            self.advance();
-        }
+    fn number(&mut self) -> Result<Token<'a>, ScanError> {
+        let radix = match self.peek() {
+            'x' | 'X' => 16,
+            'o' | 'O' => 8,
+            'b' | 'B' => 2,
+            _ => 10,
+        };
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.source.as_bytes()[self.start] == b'0' && radix != 10 {
+            self.advance();
+            if !self.peek().is_digit(radix) {
+                return self.number_error("Expect digits after number base prefix.");
+            }
             self.advance();
-            while self.peek().is_digit(10) {
+            self.digits(radix)?;
+        } else {
+            self.digits(10)?;
+
+            if self.peek() == '.' && self.peek_next().is_digit(10) {
+                self.advance();
+                self.advance();
+                self.digits(10)?;
+            }
+
+            if self.peek() == 'e' || self.peek() == 'E' {
+                self.advance();
+                if self.peek() == '+' || self.peek() == '-' {
+                    self.advance();
+                }
+                if !self.peek().is_digit(10) {
+                    return self.number_error("Expect digits in exponent.");
+                }
                 self.advance();
+                self.digits(10)?;
             }
         }
 
+        // Catches things like `0b102`, `0xFG` and `12px`
+        if Scanner::is_identifier(self.peek()) {
+            let message = format!("Unexpected character '{}' in number literal.", self.peek());
+            return self.number_error(&message);
+        }
+
         self.build_token_value(TokenType::Number)
     }
 
-    fn identifier_str(&mut self) -> String {
+    // Consumes the rest of a digit run that started with an already consumed
+    // digit. A `_` separator is only allowed between two digits.
+    fn digits(&mut self, radix: u32) -> Result<(), ScanError> {
+        loop {
+            if self.peek() == '_' {
+                if !self.peek_next().is_digit(radix) {
+                    return self.number_error("Digit separator '_' must be between digits.");
+                }
+                self.advance();
+            } else if !self.peek().is_digit(radix) {
+                return Result::Ok(());
+            }
+            self.advance();
+        }
+    }
+
+    fn number_error<T>(&mut self, message: &str) -> Result<T, ScanError> {
+        // Skip the rest of the malformed literal so it doesn't turn
+        // into more tokens
+        while Scanner::is_identifier(self.peek()) || self.peek() == '.' {
+            self.advance();
+        }
+        Result::Err(ScanError {
+            message: message.to_string(),
+            line: self.line,
+        })
+    }
+
+    fn identifier_str(&mut self) -> Cow<'a, str> {
         while self::Scanner::is_identifier(self.peek()) {
             self.advance();
         }
 
+        // `é` written precomposed or as `e` and a combining accent is the
+        // same name. Only identifiers that aren't already normalised are copied.
         let slice = &self.source[self.start..self.current];
-        slice.into_iter().collect()
+        match is_nfc_quick(slice.chars()) {
+            IsNormalized::Yes => Cow::Borrowed(slice),
+            _ => Cow::Owned(slice.nfc().collect()),
+        }
     }
 
-    fn identifier_or_keyword(&mut self) -> Result<Token, ScanError> {
+    fn identifier_or_keyword(&mut self) -> Result<Token<'a>, ScanError> {
         let id = self.identifier_str();
-        match id.as_str() {
+        match id.as_ref() {
             "and" => self.build_token(TokenType::And),
+            "as" => self.build_token(TokenType::As),
+            "catch" => self.build_token(TokenType::Catch),
             "class" => self.build_token(TokenType::Class),
+            "const" => self.build_token(TokenType::Const),
             "else" => self.build_token(TokenType::Else),
+            "enum" => self.build_token(TokenType::Enum),
+            "export" => self.build_token(TokenType::Export),
             "false" => self.build_token(TokenType::False),
+            "finally" => self.build_token(TokenType::Finally),
             "for" => self.build_token(TokenType::For),
             "fun" => self.build_token(TokenType::Fun),
             "if" => self.build_token(TokenType::If),
+            "import" => self.build_token(TokenType::Import),
+            "is" => self.build_token(TokenType::Is),
+            "match" => self.build_token(TokenType::Match),
             "nil" => self.build_token(TokenType::Nil),
             "or" => self.build_token(TokenType::Or),
             "print" => self.build_token(TokenType::Print),
             "return" => self.build_token(TokenType::Return),
             "super" => self.build_token(TokenType::Super),
             "this" => self.build_token(TokenType::This),
+            "throw" => self.build_token(TokenType::Throw),
             "true" => self.build_token(TokenType::True),
+            "try" => self.build_token(TokenType::Try),
             "var" => self.build_token(TokenType::Var),
             "while" => self.build_token(TokenType::While),
-            _ => self.build_token(TokenType::Identifier),
+            _ => Result::Ok(Token::new(TokenType::Identifier, id, self.line)),
         }
     }
 
@@ -269,16 +403,15 @@ impl Scanner {
     }
 
     fn is_identifier(character: char) -> bool {
-        character.is_alphanumeric() || character == '_'
+        is_xid_continue(character)
     }
 
-    fn build_token(&mut self, token_type: TokenType) -> Result<Token, ScanError> {
+    fn build_token(&mut self, token_type: TokenType) -> Result<Token<'a>, ScanError> {
         self.build_token_value(token_type)
     }
 
-    fn build_token_value(&mut self, token_type: TokenType) -> Result<Token, ScanError> {
-        let slice = &self.source[self.start..self.current];
-        let lexeme = slice.into_iter().collect();
+    fn build_token_value(&mut self, token_type: TokenType) -> Result<Token<'a>, ScanError> {
+        let lexeme = Cow::Borrowed(&self.source[self.start..self.current]);
         let res = Result::Ok(Token::new(token_type, lexeme, self.line));
         //println!("Created token: {:?}", res);
         res
@@ -288,13 +421,156 @@ impl Scanner {
 #[cfg(test)]
 mod tests {
     use crate::token::TokenType;
+
+    fn scan_all(source: &str) -> Vec<crate::token::Token<'_>> {
+        let mut scanner = super::Scanner::new(source);
+        let mut tokens = vec![];
+        loop {
+            let token = match scanner.scan_token() {
+                Ok(token) => token,
+                Err(e) => panic!("[line {}] {}", e.line, e.message),
+            };
+            if token.token_type == TokenType::Eof {
+                break;
+            }
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    #[test]
+    fn ranges() {
+        let tokens = scan_all("s[1..2] s[1.5..=3] s[..]");
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::LeftBracket,
+                TokenType::Number,
+                TokenType::DotDot,
+                TokenType::Number,
+                TokenType::RightBracket,
+                TokenType::Identifier,
+                TokenType::LeftBracket,
+                TokenType::Number,
+                TokenType::DotDotEqual,
+                TokenType::Number,
+                TokenType::RightBracket,
+                TokenType::Identifier,
+                TokenType::LeftBracket,
+                TokenType::DotDot,
+                TokenType::RightBracket,
+            ]
+        );
+        assert_eq!(tokens[2].lexeme, "1");
+        assert_eq!(tokens[8].lexeme, "1.5");
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let tokens = scan_all("\"a${x + {1}}b${y}c\"");
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Interpolation,
+                TokenType::Identifier,
+                TokenType::Plus,
+                TokenType::LeftBrace,
+                TokenType::Number,
+                TokenType::RightBrace,
+                TokenType::Interpolation,
+                TokenType::Identifier,
+                TokenType::String,
+            ]
+        );
+        assert_eq!(tokens[0].lexeme, "\"a${");
+        assert_eq!(tokens[6].lexeme, "}b${");
+        assert_eq!(tokens[8].lexeme, "}c\"");
+    }
+
+    #[test]
+    fn number_literals() {
+        let tokens = scan_all("12 3.25 1_000_000 0xFF 0b1010 0o17 1.5e-3 2E10 0xff_ff");
+        assert!(tokens.iter().all(|t| t.token_type == TokenType::Number));
+        let lexemes: Vec<&str> = tokens.iter().map(|t| t.lexeme.as_ref()).collect();
+        assert_eq!(
+            lexemes,
+            vec!["12", "3.25", "1_000_000", "0xFF", "0b1010", "0o17", "1.5e-3", "2E10", "0xff_ff"]
+        );
+    }
+
+    #[test]
+    fn malformed_number_literals() {
+        for source in ["0b102", "0xFG", "0x", "1_", "1__0", "1e", "1.5e+", "12px", "0x_ff"] {
+            let mut scanner = super::Scanner::new(source);
+            assert!(scanner.scan_token().is_err(), "{} should not scan", source);
+        }
+    }
+
+    #[test]
+    fn unterminated_interpolation() {
+        let mut scanner = super::Scanner::new("\"a${x");
+        assert!(scanner.scan_token().is_ok());
+        assert!(scanner.scan_token().is_ok());
+        assert!(scanner.scan_token().is_err());
+    }
+
+    #[test]
+    fn long_whitespace_runs() {
+        // Skipped in a loop rather than one call per character
+        let source = format!("{}a{}b{}c", " ".repeat(200_000), "\n".repeat(200_000), "// x\n/* y */".repeat(50_000));
+        let tokens = scan_all(&source);
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[1].line, 200_001);
+        assert_eq!(tokens[2].line, 250_001);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let tokens = scan_all("café ñandú 変数 _x1 Δt");
+        assert!(tokens.iter().all(|t| t.token_type == TokenType::Identifier));
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[2].lexeme, "変数");
+    }
+
+    #[test]
+    fn combining_characters() {
+        // `e` followed by a combining acute accent is normalised to `é`
+        let tokens = scan_all("cafe\u{301} café");
+        assert_eq!(tokens[0].lexeme, "café");
+        assert_eq!(tokens[0].lexeme, tokens[1].lexeme);
+
+        // A combining mark can't start an identifier
+        let mut scanner = super::Scanner::new("\u{301}x");
+        assert!(scanner.scan_token().is_err());
+    }
+
+    #[test]
+    fn emoji() {
+        let tokens = scan_all("\"👍🏽 ${x}👨‍👩‍👧\"");
+        assert_eq!(tokens[0].lexeme, "\"👍🏽 ${");
+        assert_eq!(tokens[2].lexeme, "}👨‍👩‍👧\"");
+
+        // Emoji aren't identifier characters
+        for source in ["😀", "x😀"] {
+            let mut scanner = super::Scanner::new(source);
+            let scanned: Vec<_> = std::iter::from_fn(|| match scanner.scan_token() {
+                Ok(token) if token.token_type == TokenType::Eof => None,
+                result => Some(result.is_ok()),
+            })
+            .collect();
+            assert!(scanned.contains(&false), "{} should not scan", source);
+        }
+    }
     /*
     #[test]
     fn random_tokens() {
         //Shamelessly stolen from https://github.com/abesto/jlox-rs/blob/main/src/scanner.rs
         let source = "(){},.-+;*!23!=42.42/*.block.\n.comment.*/
     ==<<==>/>=\"foo \nbar\"// this is a comment now";
-        let mut scanner = super::Scanner::new(source.to_string());
+        let mut scanner = super::Scanner::new(source);
         let tokens = scanner.scan_tokens();
 
         for (i, v) in [
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display};
 
 use crate::value::Value;
 
@@ -9,6 +9,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -20,33 +23,50 @@ pub enum TokenType {
     // One or two character tokens.
     Bang,
     BangEqual,
+    DotDot,
+    DotDotEqual,
     Equal,
     EqualEqual,
+    EqualGreater,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    QuestionDot,
+    QuestionQuestion,
 
     // Literals.
     Identifier,
     String,
+    Interpolation,
     Number,
 
     // Keywords.
     And,
+    As,
+    Catch,
     Class,
+    Const,
     Else,
+    Enum,
+    Export,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
+    Is,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
@@ -59,15 +79,17 @@ impl Display for TokenType {
     }
 }
 
+// The lexeme is a slice of the source, except for identifiers that had to
+// be normalised
 #[derive(Debug, Clone)]
-pub struct Token {
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Cow<'a, str>,
     pub line: u32,
 }
 
-impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, line: u32) -> Self {
+impl<'a> Token<'a> {
+    pub fn new(token_type: TokenType, lexeme: Cow<'a, str>, line: u32) -> Self {
         Token {
             token_type,
             lexeme,
@@ -76,17 +98,17 @@ impl Token {
     }
 }
 
-impl Default for Token {
+impl Default for Token<'_> {
     fn default() -> Self {
         Token {
             token_type: TokenType::Eof,
-            lexeme: "".to_string(),
+            lexeme: Cow::Borrowed(""),
             line: 0,
         }
     }
 }
 
-impl Display for Token {
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
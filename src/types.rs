@@ -0,0 +1,90 @@
+use std::fmt::Display;
+
+use crate::token::TokenType;
+
+// The types that can appear in annotations like `var x: Number = 1;`.
+// They are only used by the compiler when type checking is enabled, the VM
+// never sees them.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StaticType {
+    // Unannotated variables and expressions whose type isn't known until
+    // they run
+    Any,
+    Nil,
+    Bool,
+    Int,
+    Float,
+    // Either an Int or a Float
+    Number,
+    String,
+}
+
+impl StaticType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Any" => Some(StaticType::Any),
+            "Nil" => Some(StaticType::Nil),
+            "Bool" => Some(StaticType::Bool),
+            "Int" => Some(StaticType::Int),
+            "Float" => Some(StaticType::Float),
+            "Number" => Some(StaticType::Number),
+            "String" => Some(StaticType::String),
+            _ => None,
+        }
+    }
+
+    // Whether a value of type `other` can be stored in a variable of this type
+    pub fn accepts(self, other: StaticType) -> bool {
+        use StaticType as ST;
+        match (self, other) {
+            (ST::Any, _) | (_, ST::Any) => true,
+            (ST::Number, ST::Int | ST::Float) => true,
+            _ => self == other,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, StaticType::Int | StaticType::Float | StaticType::Number)
+    }
+
+    // The type of `-operand`
+    pub fn negate(self) -> Self {
+        if self.is_numeric() {
+            self
+        } else {
+            StaticType::Any
+        }
+    }
+
+    // The type of `left operator right`, following the rules of the VM's
+    // `binary_op!` and `compare!`
+    pub fn binary(operator: &TokenType, left: Self, right: Self) -> Self {
+        use StaticType as ST;
+        match operator {
+            TokenType::BangEqual
+            | TokenType::EqualEqual
+            | TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => ST::Bool,
+            TokenType::Plus if left == ST::String && right == ST::String => ST::String,
+            TokenType::Slash if left.is_numeric() && right.is_numeric() => ST::Float,
+            TokenType::Plus | TokenType::Minus | TokenType::Star
+                if left.is_numeric() && right.is_numeric() =>
+            {
+                match (left, right) {
+                    (ST::Int, ST::Int) => ST::Int,
+                    (ST::Float, _) | (_, ST::Float) => ST::Float,
+                    _ => ST::Number,
+                }
+            }
+            _ => ST::Any,
+        }
+    }
+}
+
+impl Display for StaticType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
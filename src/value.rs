@@ -1,11 +1,15 @@
-use std::fmt::{Debug, Display};
+use std::{
+    cmp::Ordering,
+    fmt::{Debug, Display},
+};
 
 use crate::obj::Obj;
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Nil,
     Boolean(bool),
+    Int(i64),
     Number(f64),
     Obj(Box<Obj>),
 }
@@ -15,10 +19,104 @@ impl Value {
         match self {
             Self::Nil => true,
             Self::Boolean(b) => !b,
+            Self::Int(_) => false,
             Self::Number(_) => false,
             Self::Obj(_) => false,
         }
     }
+
+    // Ints are promoted to floats when mixed with them in arithmetic
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(i) => Some(*i as f64),
+            Self::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    // The name `type(value)` returns
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Boolean(_) => "bool",
+            Self::Int(_) | Self::Number(_) => "number",
+            Self::Obj(obj) => match obj.as_ref() {
+                Obj::Str(_) => "string",
+                Obj::Error { .. } => "error",
+                Obj::Module { .. } => "module",
+                Obj::Enum { .. } => "enum",
+                Obj::Variant { .. } => "variant",
+                Obj::Range { .. } => "range",
+                Obj::Native { .. } => "native",
+            },
+        }
+    }
+
+    // Whether the value is of one of the `TYPE_NAMES`
+    pub fn is_type(&self, type_name: &str) -> bool {
+        match type_name {
+            "Int" => matches!(self, Self::Int(_)),
+            "Float" => matches!(self, Self::Number(_)),
+            _ => type_name.eq_ignore_ascii_case(self.type_name()),
+        }
+    }
+}
+
+// The built-in types that can be tested for with `value is Name`
+pub const TYPE_NAMES: [&str; 12] = [
+    "Nil", "Bool", "Number", "Int", "Float", "String", "Error", "Module", "Enum", "Variant",
+    "Range", "Native",
+];
+
+// Compares exactly instead of going through `i as f64`, which would make
+// distinct ints above 2^53 compare equal to the same float.
+fn compare_int_float(int: i64, float: f64) -> Option<Ordering> {
+    if float.is_nan() {
+        return None;
+    }
+    // 2^63 is exactly representable, i64::MAX is not
+    if float >= 9223372036854775808.0 {
+        return Some(Ordering::Less);
+    }
+    if float < -9223372036854775808.0 {
+        return Some(Ordering::Greater);
+    }
+    let truncated = float.trunc();
+    match int.cmp(&(truncated as i64)) {
+        Ordering::Equal => 0.0.partial_cmp(&(float - truncated)),
+        ordering => Some(ordering),
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::Int(a), Self::Number(b)) | (Self::Number(b), Self::Int(a)) => {
+                compare_int_float(*a, *b) == Some(Ordering::Equal)
+            }
+            (Self::Obj(a), Self::Obj(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => Some(Ordering::Equal),
+            (Self::Boolean(a), Self::Boolean(b)) => a.partial_cmp(b),
+            (Self::Int(a), Self::Int(b)) => a.partial_cmp(b),
+            (Self::Number(a), Self::Number(b)) => a.partial_cmp(b),
+            (Self::Int(a), Self::Number(b)) => compare_int_float(*a, *b),
+            (Self::Number(a), Self::Int(b)) => compare_int_float(*b, *a).map(Ordering::reverse),
+            (Self::Obj(a), Self::Obj(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
 }
 
 impl From<bool> for Value {
@@ -27,6 +125,12 @@ impl From<bool> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
 impl From<f64> for Value {
     fn from(value: f64) -> Self {
         Value::Number(value)
@@ -44,14 +148,14 @@ impl Display for Value {
         match self {
             Value::Nil => write!(f, "{}", "nil"),
             Value::Boolean(b) => write!(f, "{}", b),
-            Value::Number(float) => write!(f, "{}", *float),
+            Value::Int(int) => write!(f, "{}", int),
+            // `{:?}` keeps the `.0` of whole floats so they don't print like ints
+            Value::Number(float) => write!(f, "{:?}", *float),
             Value::Obj(o) => std::fmt::Display::fmt(&o, f),
         }
     }
 }
 
-pub type VmValue = f64;
-
 #[derive(Debug)]
 pub struct ValueArray {
     pub values: Vec<Value>,
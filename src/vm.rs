@@ -1,11 +1,18 @@
-use std::{collections::HashMap, usize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, mem,
+    path::{Path, PathBuf},
+    usize,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     chunk::{Chunk, OpCode},
     compiler::Parser,
     debug::disassemble_instruction,
-    obj::Obj,
-    value::Value,
+    obj::{NativeFn, Obj},
+    value::{Value, TYPE_NAMES},
 };
 
 macro_rules! binary_op {
@@ -27,37 +34,70 @@ macro_rules! binary_op {
                     }
                 }
             }
-            (Value::Number(x), Value::Number(y)) => {
-                let c = x + y;
-                $self.push(Value::Number(c));
-            }
-            _ => {
-                $self.runtime_error("Operands must be numbers.");
-                return Err(InterpretError::RuntimeError);
-            }
+            (a, b) => binary_op!(@numeric $self, a, b, +, checked_add),
         }
     }};
-    ($self:ident, $op:tt) => {{
+    // Division always produces a float, so `7 / 2` is `3.5`
+    ($self:ident, /) => {{
         let b = $self.pop().unwrap();
         let a = $self.pop().unwrap();
-        match (a, b) {
-            (Value::Number(x), Value::Number(y)) => {
-                let c = x $op y;
-                $self.push(Value::from(c));
-            }
+        match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => $self.push(Value::from(x / y)),
             _ => {
                 $self.runtime_error("Operands must be numbers.");
                 return Err(InterpretError::RuntimeError);
             }
         }
-    }}
+    }};
+    ($self:ident, $op:tt, $checked:ident) => {{
+        let b = $self.pop().unwrap();
+        let a = $self.pop().unwrap();
+        binary_op!(@numeric $self, a, b, $op, $checked)
+    }};
+    // Two ints stay ints and fail on overflow, otherwise both operands
+    // are promoted to floats.
+    (@numeric $self:ident, $a:ident, $b:ident, $op:tt, $checked:ident) => {{
+        match ($a, $b) {
+            (Value::Int(x), Value::Int(y)) => match x.$checked(y) {
+                Some(c) => $self.push(Value::from(c)),
+                None => {
+                    $self.runtime_error("Integer overflow.");
+                    return Err(InterpretError::RuntimeError);
+                }
+            },
+            (a, b) => match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => $self.push(Value::from(x $op y)),
+                _ => {
+                    $self.runtime_error("Operands must be numbers.");
+                    return Err(InterpretError::RuntimeError);
+                }
+            },
+        }
+    }};
 }
 
+// Only numbers (ints and floats in any mix) and strings can be ordered.
+// Comparisons involving NaN are always false.
 macro_rules! compare {
     ($self:ident, $op:tt) => {{
         let b = $self.pop().unwrap();
         let a = $self.pop().unwrap();
-        $self.push(Value::from(a $op b));
+        let result = match (&a, &b) {
+            (Value::Int(_) | Value::Number(_), Value::Int(_) | Value::Number(_)) => a $op b,
+            (Value::Obj(x), Value::Obj(y)) => match (x.as_ref(), y.as_ref()) {
+                // Lexicographic by code point
+                (Obj::Str(p), Obj::Str(q)) => p.as_str() $op q.as_str(),
+                _ => {
+                    $self.runtime_error("Operands must be two numbers or two strings.");
+                    return Err(InterpretError::RuntimeError);
+                }
+            },
+            _ => {
+                $self.runtime_error("Operands must be two numbers or two strings.");
+                return Err(InterpretError::RuntimeError);
+            }
+        };
+        $self.push(Value::from(result));
     }};
 }
 
@@ -66,23 +106,82 @@ pub enum InterpretError {
     RuntimeError,
 }
 
+// Installed by OpTry, says where to continue when an exception is thrown
+struct Handler {
+    catch_ip: usize,
+    stack_height: usize,
+}
+
 pub struct VM {
     stack: Vec<Value>,
     top: usize,
     ip: usize,
     chunk: Chunk,
     globals: HashMap<String, Value>,
+    const_globals: HashSet<String>,
+    handlers: Vec<Handler>,
+    // The value being thrown and the line it was thrown from
+    exception: Option<(Value, u32)>,
+    // Globals declared with `export` in the code this VM runs
+    exports: HashSet<String>,
+    // Relative imports are looked up here first, then in `search_paths`
+    base_dir: PathBuf,
+    search_paths: Vec<PathBuf>,
+    // Loaded modules by canonical path, and the chain of modules currently
+    // being loaded to detect import cycles. Both are handed down to the VMs
+    // that run imported modules.
+    modules: HashMap<PathBuf, Value>,
+    loading: Vec<PathBuf>,
+    // Report values that don't match the annotated type of their variable
+    // as compile errors
+    typecheck: bool,
 }
 
 impl VM {
     pub fn new() -> VM {
-        VM {
+        let mut vm = VM {
             stack: vec![],
             top: 1,
             ip: 0,
             chunk: Chunk::new(),
             globals: HashMap::new(),
+            const_globals: HashSet::new(),
+            handlers: vec![],
+            exception: None,
+            exports: HashSet::new(),
+            base_dir: PathBuf::from("."),
+            search_paths: vec![],
+            modules: HashMap::new(),
+            loading: vec![],
+            typecheck: false,
+        };
+        vm.define_native("type", 1, type_native);
+        vm.define_native("floor", 1, floor_native);
+        vm
+    }
+
+    fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let native = Obj::Native { name: name.to_string(), arity, function };
+        self.globals.insert(name.to_string(), Value::from(native));
+    }
+
+    // Makes imports in the script relative to its directory and lets
+    // modules importing the script back be reported as a cycle
+    pub fn set_script_path(&mut self, path: &Path) {
+        if let Some(parent) = path.parent() {
+            self.base_dir = parent.to_path_buf();
         }
+        if let Ok(canonical) = path.canonicalize() {
+            self.loading = vec![canonical];
+        }
+    }
+
+    pub fn add_search_path(&mut self, path: PathBuf) {
+        self.search_paths.push(path);
+    }
+
+    pub fn set_typecheck(&mut self, typecheck: bool) {
+        self.typecheck = typecheck;
     }
 
     pub fn reset(&mut self) {
@@ -95,7 +194,9 @@ impl VM {
     }
 
     pub fn pop(&mut self) -> Option<Value> {
-        self.stack.pop()
+        let value = self.stack.pop();
+        self.top = self.stack.len();
+        value
     }
 
     pub fn peek(&self, depth: usize) -> Option<&Value> {
@@ -118,9 +219,14 @@ impl VM {
     }
 
     pub fn interpret(&mut self, source: String) -> Result<(), InterpretError> {
-        let mut parser = Parser::new(source);
+        let mut parser = Parser::new(&source);
+        parser.set_typecheck(self.typecheck);
+        // Code is appended to the chunk of earlier REPL lines
+        let start = self.chunk.code.len();
         let compilation_result = parser.compile(&mut self.chunk);
         if let Err(_) = compilation_result {
+            self.chunk.code.truncate(start);
+            self.chunk.lines.truncate(start);
             Err(InterpretError::CompileError)
         } else {
             self.run()
@@ -128,6 +234,42 @@ impl VM {
     }
 
     pub fn run(&mut self) -> Result<(), InterpretError> {
+        loop {
+            let result = self.execute();
+            if result.is_ok() {
+                return result;
+            }
+            let Some((exception, line)) = self.exception.take() else {
+                return result;
+            };
+            match self.handlers.pop() {
+                Some(handler) => {
+                    // Discard the locals and temporaries of the try block
+                    self.stack.truncate(handler.stack_height);
+                    self.push(exception);
+                    self.push(Value::from(line as i64));
+                    self.ip = handler.catch_ip;
+                }
+                None => {
+                    match &exception {
+                        Value::Obj(obj) => match obj.as_ref() {
+                            Obj::Error { message, .. } => eprintln!("{}", message),
+                            _ => eprintln!("Uncaught exception: {}", exception),
+                        },
+                        _ => eprintln!("Uncaught exception: {}", exception),
+                    }
+                    eprintln!("[line {}] in script", line);
+                    // Skip the rest of the failed code so the next REPL line
+                    // starts from a clean stack
+                    self.ip = self.chunk.code.len();
+                    self.stack.clear();
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), InterpretError> {
         while self.ip < self.chunk.code.len() {
             if cfg!(feature = "DEBUG_TRACE_EXECUTION") {
                 print!("        ");
@@ -174,10 +316,17 @@ impl VM {
                             }
                         }
                     }
-                    OC::OpDefineGlobal => {
+                    OC::OpDefineGlobal | OC::OpDefineConstGlobal => {
                         let constant: Value = self.read_constant();
                         if let Value::Obj(a) = constant {
                             if let Obj::Str(name) = *a {
+                                if self.const_globals.contains(&name) {
+                                    self.runtime_error(&format!("Can't redefine constant '{}'.", &name));
+                                    return Err(InterpretError::RuntimeError);
+                                }
+                                if code == OC::OpDefineConstGlobal {
+                                    self.const_globals.insert(name.clone());
+                                }
                                 let value = self.pop();
                                 self.globals.insert(name.clone(), value.unwrap());
                             }
@@ -187,6 +336,10 @@ impl VM {
                         let constant: Value = self.read_constant();
                         if let Value::Obj(a) = constant {
                             if let Obj::Str(name) = *a {
+                                if self.const_globals.contains(&name) {
+                                    self.runtime_error(&format!("Can't assign to constant '{}'.", &name));
+                                    return Err(InterpretError::RuntimeError);
+                                }
                                 let top = self.peek(0).unwrap().clone();
                                 let previous_value = self.globals.insert(name.clone(), top);
                                 // If previous value did not exist
@@ -199,16 +352,47 @@ impl VM {
                             }
                         }
                     }
+                    OC::OpGetProperty => {
+                        let constant: Value = self.read_constant();
+                        let object = self.pop().unwrap();
+                        if let (Value::Obj(name), Value::Obj(object)) = (&constant, &object) {
+                            if let Obj::Str(name) = name.as_ref() {
+                                if let Some(value) = object.get_property(name) {
+                                    self.push(value);
+                                    continue;
+                                }
+                            }
+                        }
+                        self.runtime_error(&format!("Undefined property '{}' on {}.", constant, object));
+                        return Err(InterpretError::RuntimeError);
+                    }
+                    OC::OpImport => {
+                        let constant: Value = self.read_constant();
+                        let path = constant.to_string();
+                        match self.import_module(&path) {
+                            Ok(module) => self.push(module),
+                            Err(message) => {
+                                self.runtime_error(&message);
+                                return Err(InterpretError::RuntimeError);
+                            }
+                        }
+                    }
+                    OC::OpExport => {
+                        let constant: Value = self.read_constant();
+                        self.exports.insert(constant.to_string());
+                    }
                     OC::OpEqual => {
                         let b = self.pop();
                         let a = self.pop();
                         self.push(Value::from(b == a))
                     }
                     OC::OpGreater => compare!(self, >),
+                    OC::OpGreaterEqual => compare!(self, >=),
                     OC::OpLess => compare!(self, <),
+                    OC::OpLessEqual => compare!(self, <=),
                     OC::OpAdd => binary_op!(self, +),
-                    OC::OpSubtract => binary_op!(self, -),
-                    OC::OpMultiply => binary_op!(self, *),
+                    OC::OpSubtract => binary_op!(self, -, checked_sub),
+                    OC::OpMultiply => binary_op!(self, *, checked_mul),
                     OC::OpDivide => binary_op!(self, /),
                     OC::OpNot => {
                         if let Some(b) = self.pop() {
@@ -217,11 +401,19 @@ impl VM {
                     }
                     OC::OpNegate => {
                         if let Some(value) = self.pop() {
-                            if let Value::Number(num) = value {
-                                self.push(Value::from(-num));
-                            } else {
-                                self.runtime_error("Operand must be a number.");
-                                return Err(InterpretError::RuntimeError);
+                            match value {
+                                Value::Int(int) => match int.checked_neg() {
+                                    Some(negated) => self.push(Value::from(negated)),
+                                    None => {
+                                        self.runtime_error("Integer overflow.");
+                                        return Err(InterpretError::RuntimeError);
+                                    }
+                                },
+                                Value::Number(num) => self.push(Value::from(-num)),
+                                _ => {
+                                    self.runtime_error("Operand must be a number.");
+                                    return Err(InterpretError::RuntimeError);
+                                }
                             }
                         }
                     }
@@ -229,6 +421,16 @@ impl VM {
                         let value = self.pop();
                         println!("{}", value.unwrap());
                     }
+                    OC::OpStringify => {
+                        let value = self.pop().unwrap();
+                        if let Value::Obj(obj) = &value {
+                            if let Obj::Str(_) = obj.as_ref() {
+                                self.push(value);
+                                continue;
+                            }
+                        }
+                        self.push(Value::from(Obj::from(value.to_string())));
+                    }
                     OC::OpJump => {
                         let offset = self.read_two_bytes();
                         self.ip += offset as usize;
@@ -244,10 +446,204 @@ impl VM {
                             self.ip += offset as usize;
                         }
                     }
+                    OC::OpRange => {
+                        let inclusive = self.read_byte() == 1;
+                        let end = self.pop().unwrap();
+                        let start = self.pop().unwrap();
+                        let (Some(start), Some(end)) = (range_bound(&start), range_bound(&end)) else {
+                            self.runtime_error("Range bounds must be integers.");
+                            return Err(InterpretError::RuntimeError);
+                        };
+                        self.push(Value::from(Obj::Range { start, end, inclusive }));
+                    }
+                    OC::OpIndex => {
+                        let index = self.pop().unwrap();
+                        let target = self.pop().unwrap();
+                        let result = match &target {
+                            Value::Obj(obj) => match obj.as_ref() {
+                                Obj::Str(string) => index_string(string, &index),
+                                _ => Err(format!("Can't index {}.", target)),
+                            },
+                            _ => Err(format!("Can't index {}.", target)),
+                        };
+                        match result {
+                            Ok(value) => self.push(value),
+                            Err(message) => {
+                                self.runtime_error(&message);
+                                return Err(InterpretError::RuntimeError);
+                            }
+                        }
+                    }
+                    OC::OpJumpIfNil => {
+                        let offset = self.read_two_bytes();
+                        if let Some(Value::Nil) = self.peek(0) {
+                            self.ip += offset as usize;
+                        }
+                    }
                     OC::OpLoop => {
                         let offset = self.read_two_bytes();
                         self.ip -= offset as usize;
                     }
+                    OC::OpCall => {
+                        let arg_count = self.read_byte() as usize;
+                        let callee_slot = self.stack.len() - arg_count - 1;
+                        let callee = self.stack[callee_slot].clone();
+                        if let Value::Obj(obj) = callee {
+                            if let Obj::Native { arity, function, .. } = *obj {
+                                if arg_count != arity {
+                                    self.runtime_error(&format!(
+                                        "Expected {} arguments but got {}.",
+                                        arity, arg_count
+                                    ));
+                                    return Err(InterpretError::RuntimeError);
+                                }
+                                let result = match function(&self.stack[callee_slot + 1..]) {
+                                    Ok(result) => result,
+                                    Err(message) => {
+                                        self.runtime_error(&message);
+                                        return Err(InterpretError::RuntimeError);
+                                    }
+                                };
+                                self.stack.truncate(callee_slot);
+                                self.push(result);
+                                continue;
+                            }
+                            if let Obj::Variant { enum_name, name, fields, values } = *obj {
+                                if values.is_empty() && !fields.is_empty() {
+                                    if arg_count != fields.len() {
+                                        self.runtime_error(&format!(
+                                            "Expected {} arguments but got {}.",
+                                            fields.len(),
+                                            arg_count
+                                        ));
+                                        return Err(InterpretError::RuntimeError);
+                                    }
+                                    let values = self.stack.split_off(callee_slot + 1);
+                                    self.pop();
+                                    self.push(Value::from(Obj::Variant { enum_name, name, fields, values }));
+                                    continue;
+                                }
+                            }
+                        }
+                        self.runtime_error("Can only call native functions and enum variants with fields.");
+                        return Err(InterpretError::RuntimeError);
+                    }
+                    OC::OpIs => {
+                        let type_index = self.read_byte();
+                        let is_type = if type_index != u8::MAX {
+                            let value = self.pop().unwrap();
+                            value.is_type(TYPE_NAMES[type_index as usize])
+                        } else {
+                            let type_value = self.pop().unwrap();
+                            let value = self.pop().unwrap();
+                            let variant = match &value {
+                                Value::Obj(obj) => match obj.as_ref() {
+                                    Obj::Variant { enum_name, name, .. } => Some((enum_name, name)),
+                                    _ => None,
+                                },
+                                _ => None,
+                            };
+                            match &type_value {
+                                Value::Obj(obj) => match obj.as_ref() {
+                                    Obj::Enum { name: type_name, .. } => {
+                                        variant.is_some_and(|(enum_name, _)| enum_name == type_name)
+                                    }
+                                    Obj::Variant { enum_name: type_enum, name: type_name, .. } => variant
+                                        .is_some_and(|(enum_name, name)| {
+                                            enum_name == type_enum && name == type_name
+                                        }),
+                                    _ => {
+                                        self.runtime_error("Right operand of 'is' must be a type.");
+                                        return Err(InterpretError::RuntimeError);
+                                    }
+                                },
+                                _ => {
+                                    self.runtime_error("Right operand of 'is' must be a type.");
+                                    return Err(InterpretError::RuntimeError);
+                                }
+                            }
+                        };
+                        self.push(Value::from(is_type));
+                    }
+                    OC::OpIsVariant => {
+                        let field_count = self.read_byte();
+                        let pattern = self.pop().unwrap();
+                        let value = self.pop().unwrap();
+                        let Value::Obj(pattern) = pattern else {
+                            self.runtime_error("Pattern must be an enum variant.");
+                            return Err(InterpretError::RuntimeError);
+                        };
+                        let Obj::Variant { enum_name, name, fields, .. } = pattern.as_ref() else {
+                            self.runtime_error("Pattern must be an enum variant.");
+                            return Err(InterpretError::RuntimeError);
+                        };
+                        if field_count != u8::MAX && field_count as usize != fields.len() {
+                            self.runtime_error(&format!(
+                                "Pattern for {}.{} has {} fields but the variant has {}.",
+                                enum_name,
+                                name,
+                                field_count,
+                                fields.len()
+                            ));
+                            return Err(InterpretError::RuntimeError);
+                        }
+                        let is_variant = match &value {
+                            Value::Obj(obj) => match obj.as_ref() {
+                                Obj::Variant { enum_name: e, name: n, values, .. } => {
+                                    e == enum_name && n == name && values.len() == fields.len()
+                                }
+                                _ => false,
+                            },
+                            _ => false,
+                        };
+                        self.push(Value::from(is_variant));
+                    }
+                    OC::OpGetVariantField => {
+                        let index = self.read_byte() as usize;
+                        let value = self.pop().unwrap();
+                        let field = match &value {
+                            Value::Obj(obj) => match obj.as_ref() {
+                                Obj::Variant { values, .. } => values.get(index).cloned(),
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+                        match field {
+                            Some(field) => self.push(field),
+                            None => {
+                                self.runtime_error(&format!("{} has no field {}.", value, index));
+                                return Err(InterpretError::RuntimeError);
+                            }
+                        }
+                    }
+                    OC::OpNoMatch => {
+                        let value = self.stack.last().cloned().unwrap_or(Value::Nil);
+                        self.runtime_error(&format!("No match arm matched {}.", value));
+                        return Err(InterpretError::RuntimeError);
+                    }
+                    OC::OpTry => {
+                        let offset = self.read_two_bytes();
+                        self.handlers.push(Handler {
+                            catch_ip: self.ip + offset as usize,
+                            stack_height: self.stack.len(),
+                        });
+                    }
+                    OC::OpPopHandler => {
+                        self.handlers.pop();
+                    }
+                    OC::OpThrow => {
+                        let value = self.pop().unwrap();
+                        return self.throw(value);
+                    }
+                    OC::OpEndFinally => {
+                        // The line is false when no exception is pending
+                        let line = self.pop().unwrap();
+                        let exception = self.pop().unwrap();
+                        if let Value::Int(line) = line {
+                            self.exception = Some((exception, line as u32));
+                            return Err(InterpretError::RuntimeError);
+                        }
+                    }
                     OC::OpReturn => {
                         return Ok(());
                     }
@@ -259,8 +655,265 @@ impl VM {
         return Ok(());
     }
 
+    fn resolve_module(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            return path.canonicalize().ok();
+        }
+        std::iter::once(&self.base_dir)
+            .chain(self.search_paths.iter())
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.is_file())
+            .and_then(|found| found.canonicalize().ok())
+    }
+
+    fn import_module(&mut self, path: &str) -> Result<Value, String> {
+        let resolved = self
+            .resolve_module(path)
+            .ok_or_else(|| format!("Could not find module '{}'.", path))?;
+        if let Some(module) = self.modules.get(&resolved) {
+            return Ok(module.clone());
+        }
+        if self.loading.contains(&resolved) {
+            let chain: Vec<String> = self
+                .loading
+                .iter()
+                .chain(std::iter::once(&resolved))
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(format!("Import cycle: {}.", chain.join(" -> ")));
+        }
+        let source = fs::read_to_string(&resolved)
+            .map_err(|e| format!("Could not read module '{}': {}.", path, e))?;
+
+        // Every module gets a VM of its own, and with it its own globals
+        let mut module_vm = VM::new();
+        module_vm.base_dir = resolved.parent().map(Path::to_path_buf).unwrap_or_default();
+        module_vm.search_paths = self.search_paths.clone();
+        module_vm.typecheck = self.typecheck;
+        module_vm.modules = mem::take(&mut self.modules);
+        module_vm.loading = mem::take(&mut self.loading);
+        module_vm.loading.push(resolved.clone());
+
+        let result = module_vm.interpret(source);
+
+        self.modules = mem::take(&mut module_vm.modules);
+        self.loading = mem::take(&mut module_vm.loading);
+        self.loading.pop();
+        if result.is_err() {
+            return Err(format!("Could not load module '{}'.", path));
+        }
+
+        let exports = module_vm
+            .exports
+            .iter()
+            .filter_map(|name| Some((name.clone(), module_vm.globals.get(name)?.clone())))
+            .collect();
+        let module = Value::from(Obj::Module { name: path.to_string(), exports });
+        self.modules.insert(resolved, module.clone());
+        Ok(module)
+    }
+
+    fn throw(&mut self, value: Value) -> Result<(), InterpretError> {
+        let line = self.chunk.lines[self.ip - 1];
+        self.exception = Some((value, line));
+        Err(InterpretError::RuntimeError)
+    }
+
+    // Runtime errors are thrown as error objects so scripts can catch them,
+    // they are only reported if nothing does.
     fn runtime_error(&mut self, message: &str) {
-        eprintln!("{}", message);
-        eprintln!("[line {}] in script", self.chunk.lines[self.ip - 1])
+        let line = self.chunk.lines[self.ip - 1];
+        let error = Obj::Error { message: message.to_string(), line };
+        self.exception = Some((Value::from(error), line));
+    }
+}
+
+fn type_native(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::from(Obj::from(args[0].type_name().to_string())))
+}
+
+// Rounds down to an int, so the result of `/` can be used as an index or a
+// range bound
+fn floor_native(args: &[Value]) -> Result<Value, String> {
+    match args[0] {
+        Value::Int(int) => Ok(Value::from(int)),
+        Value::Number(float) => {
+            let floored = float.floor();
+            // 2^63 is exactly representable, i64::MAX is not
+            if (-9223372036854775808.0..9223372036854775808.0).contains(&floored) {
+                Ok(Value::from(floored as i64))
+            } else {
+                Err(format!("Can't floor {} to an integer.", args[0]))
+            }
+        }
+        _ => Err("Argument must be a number.".to_string()),
+    }
+}
+
+// A missing bound is nil
+fn range_bound(value: &Value) -> Option<Option<i64>> {
+    match value {
+        Value::Nil => Some(None),
+        Value::Int(bound) => Some(Some(*bound)),
+        _ => None,
+    }
+}
+
+// Strings are indexed and sliced by grapheme cluster, so an emoji or a
+// letter followed by combining accents is a single element
+fn index_string(string: &str, index: &Value) -> Result<Value, String> {
+    let graphemes: Vec<&str> = string.graphemes(true).collect();
+    let length = graphemes.len() as i64;
+    let (start, end) = match index {
+        Value::Int(i) => {
+            if *i < 0 || *i >= length {
+                return Err(format!("Index {} out of range for length {}.", i, length));
+            }
+            (*i, *i + 1)
+        }
+        Value::Obj(obj) => match obj.as_ref() {
+            Obj::Range { start, end, inclusive } => {
+                let start = start.unwrap_or(0);
+                let end = match end {
+                    Some(end) if *inclusive => end.saturating_add(1),
+                    Some(end) => *end,
+                    None => length,
+                };
+                if start < 0 || end > length || start > end {
+                    return Err(format!("Slice {} out of range for length {}.", index, length));
+                }
+                (start, end)
+            }
+            _ => return Err("Index must be an integer or a range.".to_string()),
+        },
+        _ => return Err("Index must be an integer or a range.".to_string()),
+    };
+    let slice = graphemes[start as usize..end as usize].concat();
+    Ok(Value::from(Obj::from(slice)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{index_string, InterpretError, VM};
+    use crate::{obj::Obj, value::Value};
+
+    // The samples check their own results and throw when something is off
+    #[test]
+    fn samples() {
+        for entry in fs::read_dir("samples").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "lox") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).unwrap();
+            let mut vm = VM::new();
+            vm.set_script_path(&path);
+            assert!(vm.interpret(source).is_ok(), "{} failed", path.display());
+        }
+    }
+
+    fn compile_error(source: &str) -> bool {
+        matches!(VM::new().interpret(source.to_string()), Err(InterpretError::CompileError))
+    }
+
+    #[test]
+    fn multiple_assignment_errors() {
+        assert!(compile_error("var a; var b; a, b = 1;"));
+        assert!(compile_error("var a; var b; a, b = 1, 2, 3;"));
+        assert!(compile_error("const a = 1; var b; a, b = 2, 3;"));
+        assert!(compile_error("{ var a; const b = 1; a, b = 2, 3; }"));
+    }
+
+    #[test]
+    fn constants() {
+        assert!(compile_error("const x = 1; x = 2;"));
+        assert!(compile_error("{ const x = 1; x = 2; }"));
+        assert!(compile_error("const x = 1; { x = 2; }"));
+        assert!(!compile_error("const x = 1; { var x = 2; x = 3; }"));
+
+        // Constants from earlier REPL lines are only known to the VM
+        let mut vm = VM::new();
+        assert!(vm.interpret("const x = 1;".to_string()).is_ok());
+        assert!(matches!(vm.interpret("x = 2;".to_string()), Err(InterpretError::RuntimeError)));
+        assert!(matches!(vm.interpret("var x = 3;".to_string()), Err(InterpretError::RuntimeError)));
+        assert!(matches!(vm.interpret("const x = 3;".to_string()), Err(InterpretError::RuntimeError)));
+        assert!(vm.interpret("if (x != 1) throw x;".to_string()).is_ok());
+        let source = "try { x = 2; } catch (e) { if (e.message != \"Can't assign to constant 'x'.\") throw e; }";
+        assert!(vm.interpret(source.to_string()).is_ok());
+    }
+
+    // Type mismatches are only errors with typecheck enabled, the same code
+    // runs fine without it
+    #[test]
+    fn typecheck() {
+        let mismatches = [
+            "var x: Int = \"s\";",
+            "var x: Int;",
+            "const x: String = 1;",
+            "{ const x: Bool = nil; }",
+            "var x: Int = 1; x = 1.5;",
+            "{ var x: String = \"s\"; x = 1 + 2; }",
+            "var a: Int = 1; var b: String = \"s\"; a, b = b, a;",
+            "{ var a: Float = 1.5; var b: Int = 1; a, b = b, a; }",
+        ];
+        for source in mismatches {
+            let mut vm = VM::new();
+            vm.set_typecheck(true);
+            let result = vm.interpret(source.to_string());
+            assert!(matches!(result, Err(InterpretError::CompileError)), "{} compiled", source);
+            assert!(VM::new().interpret(source.to_string()).is_ok(), "{} failed", source);
+        }
+
+        let mut vm = VM::new();
+        vm.set_typecheck(true);
+        let source = "var x: Number = 1; x = 2.5; var s: String = \"a\" + \"b\"; var n: Nil; var a: Any;";
+        assert!(vm.interpret(source.to_string()).is_ok());
+    }
+
+    #[test]
+    fn enum_named_after_builtin_type() {
+        assert!(compile_error("enum Error { Bad }"));
+        assert!(compile_error("{ enum Int { One } }"));
+        assert!(!compile_error("enum Errors { Bad } print Errors.Bad is Errors;"));
+    }
+
+    fn slice(string: &str, index: Value) -> String {
+        match index_string(string, &index) {
+            Ok(value) => value.to_string(),
+            Err(message) => panic!("{}", message),
+        }
+    }
+
+    fn range(start: i64, end: i64) -> Value {
+        Value::from(Obj::Range { start: Some(start), end: Some(end), inclusive: false })
+    }
+
+    #[test]
+    fn index_by_grapheme() {
+        // Skin tone modifiers, ZWJ sequences and combining accents are
+        // single graphemes
+        let string = "a👍🏽👨‍👩‍👧e\u{301}z";
+        assert_eq!(slice(string, Value::from(1)), "👍🏽");
+        assert_eq!(slice(string, Value::from(2)), "👨‍👩‍👧");
+        // Slices are new strings, so they come out in NFC
+        assert_eq!(slice(string, Value::from(3)), "\u{e9}");
+        assert_eq!(slice(string, range(3, 5)), "\u{e9}z");
+        assert!(index_string(string, &Value::from(5)).is_err());
+        assert_eq!(Obj::from(string.to_string()).get_property("length"), Some(Value::from(5)));
+    }
+
+    #[test]
+    fn strings_are_nfc() {
+        assert_eq!(Obj::from("e\u{301}".to_string()), Obj::from("\u{e9}".to_string()));
+        // No precomposed form for this one, the marks are only reordered
+        assert_eq!(Obj::from("q\u{307}\u{323}".to_string()), Obj::Str("q\u{323}\u{307}".to_string()));
+
+        let source = "var a = \"e\u{301}\"; var b = \"\u{e9}\";
+            if (a != b or \"e\" + \"\u{301}\" != b or \"${a}\" != b) throw a;
+            if (a.length != 1 or a[0] != b or !(a <= b and a >= b)) throw a;";
+        assert!(VM::new().interpret(source.to_string()).is_ok());
     }
 }